@@ -2,7 +2,10 @@ use clap::ArgMatches;
 use clap::{App, AppSettings, Arg, SubCommand};
 use std::time::Duration;
 
+const DIFF_COM: &'static str = "diff";
 const HTTP_COM: &'static str = "http";
+const PARSE_COM: &'static str = "parse";
+const PCAP_COM: &'static str = "pcap";
 const SMB_COM: &'static str = "smb";
 
 fn args() -> App<'static, 'static> {
@@ -13,6 +16,9 @@ fn args() -> App<'static, 'static> {
         .setting(AppSettings::SubcommandRequired)
         .subcommand(smb_command())
         .subcommand(http_command())
+        .subcommand(parse_command())
+        .subcommand(pcap_command())
+        .subcommand(diff_command())
 }
 
 fn smb_command() -> App<'static, 'static> {
@@ -36,12 +42,14 @@ fn smb_command() -> App<'static, 'static> {
                 .validator(is_usize_major_than_zero),
         )
         .arg(
-            Arg::with_name("json")
-                .long("json")
-                .short("j")
-                .takes_value(true)
-                .help("File to save output in json format")
+            Arg::with_name("all-ips")
+                .long("all-ips")
+                .help("Retrieve the challenge of every IP of the hostnames, instead of only the first one"),
         )
+        .args(&exclude_args())
+        .args(&dns_args())
+        .args(&discovery_args())
+        .args(&output_args())
         .arg(
             Arg::with_name("verbosity")
                 .short("v")
@@ -81,12 +89,14 @@ fn http_command() -> App<'static, 'static> {
                 .validator(is_usize_major_than_zero),
         )
         .arg(
-            Arg::with_name("json")
-                .long("json")
-                .short("j")
-                .takes_value(true)
-                .help("File to save output in json format")
+            Arg::with_name("all-ips")
+                .long("all-ips")
+                .help("Retrieve the challenge of every IP of the hostnames of the URLs, instead of only the first one"),
         )
+        .args(&exclude_args())
+        .args(&dns_args())
+        .args(&discovery_args())
+        .args(&output_args())
         .arg(
             Arg::with_name("verbosity")
                 .short("v")
@@ -105,6 +115,229 @@ fn http_command() -> App<'static, 'static> {
         )
 }
 
+fn parse_command() -> App<'static, 'static> {
+    SubCommand::with_name(PARSE_COM)
+        .about("Parse NTLM messages given in base64 or hex")
+        .arg(
+            Arg::with_name("message")
+                .takes_value(true)
+                .multiple(true)
+                .value_name("message")
+                .help("The NTLM messages, raw or wrapped in SPNEGO, or HTTP authentication headers. If none, stdin is used."),
+        )
+        .args(&output_args())
+        .arg(
+            Arg::with_name("verbosity")
+                .short("v")
+                .multiple(true)
+                .help("Verbosity"),
+        )
+}
+
+fn pcap_command() -> App<'static, 'static> {
+    SubCommand::with_name(PCAP_COM)
+        .about("Extract the NTLM challenges from pcap/pcapng captures")
+        .arg(
+            Arg::with_name("file")
+                .takes_value(true)
+                .multiple(true)
+                .required(true)
+                .value_name("file")
+                .help("The capture files. Streams split across files are reassembled."),
+        )
+        .args(&output_args())
+        .arg(
+            Arg::with_name("verbosity")
+                .short("v")
+                .multiple(true)
+                .help("Verbosity"),
+        )
+}
+
+fn diff_command() -> App<'static, 'static> {
+    SubCommand::with_name(DIFF_COM)
+        .about("Compare the results of two scans")
+        .arg(
+            Arg::with_name("old")
+                .takes_value(true)
+                .value_name("old")
+                .required_unless("db")
+                .help("The old JSON results file, or scan ID with --db"),
+        )
+        .arg(
+            Arg::with_name("new")
+                .takes_value(true)
+                .value_name("new")
+                .required_unless("db")
+                .help("The new JSON results file, or scan ID with --db"),
+        )
+        .arg(
+            Arg::with_name("db")
+                .long("db")
+                .takes_value(true)
+                .value_name("file")
+                .help("Compare scans of a results database. If no scan IDs are given, the last two scans are compared"),
+        )
+        .arg(
+            Arg::with_name("verbosity")
+                .short("v")
+                .multiple(true)
+                .help("Verbosity"),
+        )
+}
+
+/// Arguments to exclude targets, shared by the commands that scan.
+fn exclude_args() -> Vec<Arg<'static, 'static>> {
+    vec![
+        Arg::with_name("exclude")
+            .long("exclude")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1)
+            .value_name("host/range")
+            .help(
+                "Hosts or IP ranges to exclude, like '10.0.0.5,10.0.0.100-120'",
+            ),
+        Arg::with_name("exclude-file")
+            .long("exclude-file")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1)
+            .value_name("file")
+            .help("File with hosts or IP ranges to exclude, one per line"),
+    ]
+}
+
+/// Arguments to configure the resolution of hostnames and the DNS check.
+fn dns_args() -> Vec<Arg<'static, 'static>> {
+    vec![
+        Arg::with_name("dns-check")
+            .long("dns-check")
+            .help("Compare the PTR of the IP and the IPs of the DNS computer name with the challenge"),
+        Arg::with_name("dns-server")
+            .long("dns-server")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1)
+            .value_name("ip[:port]")
+            .help("DNS server to resolve the hostnames, instead of the system ones"),
+        Arg::with_name("dns-tcp")
+            .long("dns-tcp")
+            .help("Use TCP for the DNS queries"),
+        Arg::with_name("dns-search")
+            .long("dns-search")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1)
+            .value_name("domain")
+            .help("Domain to append to the hostnames that are not fully qualified"),
+    ]
+}
+
+/// Arguments to scan the hosts discovered from the challenges.
+fn discovery_args() -> Vec<Arg<'static, 'static>> {
+    vec![
+        Arg::with_name("discover")
+            .long("discover")
+            .help("Query the SRV records of the domains found in the challenges to scan their domain controllers and global catalogs with SMB"),
+        Arg::with_name("recursive")
+            .long("recursive")
+            .short("r")
+            .requires("scope")
+            .help("Scan the names found in the challenges (with SMB), redirects and TLS certificates (with HTTP), until no new hosts appear. Requires --scope"),
+        Arg::with_name("scope")
+            .long("scope")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1)
+            .value_name("range/domain")
+            .help("IP ranges or domain suffixes of the names to follow in recursive mode, like '10.0.0.0/8,contoso.local'. Required by --recursive"),
+        Arg::with_name("max-depth")
+            .long("max-depth")
+            .takes_value(true)
+            .default_value("3")
+            .value_name("n")
+            .validator(is_usize_major_than_zero)
+            .help("Maximum number of rounds of discovered hosts"),
+    ]
+}
+
+/// Arguments to control the output, shared by all the commands.
+fn output_args() -> Vec<Arg<'static, 'static>> {
+    vec![
+        Arg::with_name("json")
+            .long("json")
+            .short("j")
+            .takes_value(true)
+            .value_name("file")
+            .help("File to save output in json format"),
+        Arg::with_name("jsonl")
+            .long("jsonl")
+            .takes_value(true)
+            .value_name("file")
+            .help("File to save output in JSON Lines format, written as results arrive"),
+        Arg::with_name("csv")
+            .long("csv")
+            .takes_value(true)
+            .value_name("file")
+            .help("File to save output in CSV format"),
+        Arg::with_name("tsv")
+            .long("tsv")
+            .takes_value(true)
+            .value_name("file")
+            .help("File to save output in TSV format"),
+        Arg::with_name("columns")
+            .long("columns")
+            .takes_value(true)
+            .value_name("col1,col2")
+            .help("Columns of the CSV/TSV output, in order. Any JSON field can be used"),
+        Arg::with_name("bloodhound")
+            .long("bloodhound")
+            .takes_value(true)
+            .value_name("dir")
+            .help("Directory to save the computers and domains in BloodHound JSON format"),
+        Arg::with_name("db")
+            .long("db")
+            .takes_value(true)
+            .value_name("file")
+            .help("SQLite database to save the results, keeping previous scans"),
+        Arg::with_name("html")
+            .long("html")
+            .takes_value(true)
+            .value_name("file")
+            .help("File to save a self-contained HTML report"),
+        Arg::with_name("nmap")
+            .long("nmap")
+            .takes_value(true)
+            .value_name("file")
+            .help("File to save output in nmap XML format"),
+        Arg::with_name("hosts")
+            .long("hosts")
+            .takes_value(true)
+            .value_name("file")
+            .help("File to save the discovered names as /etc/hosts lines"),
+        Arg::with_name("zone")
+            .long("zone")
+            .takes_value(true)
+            .value_name("file")
+            .help("File to save the discovered names as BIND A/PTR records"),
+        Arg::with_name("full")
+            .long("full")
+            .short("f")
+            .help("Show all the fields of the NTLM challenge"),
+        Arg::with_name("summary")
+            .long("summary")
+            .short("s")
+            .help("Show a summary of the hosts of each domain at the end"),
+        Arg::with_name("format")
+            .long("format")
+            .takes_value(true)
+            .value_name("template")
+            .conflicts_with("full")
+            .help("Print each challenge with a template, like '{ip}\\t{dns_computer}'. Any JSON field can be used"),
+    ]
+}
+
 fn is_usize_major_than_zero(v: String) -> Result<(), String> {
     match v.parse::<usize>() {
         Ok(uint) => {
@@ -120,7 +353,10 @@ fn is_usize_major_than_zero(v: String) -> Result<(), String> {
 }
 
 pub enum Args {
+    Diff(DiffArgs),
     Http(HttpArgs),
+    Parse(ParseArgs),
+    Pcap(PcapArgs),
     Smb(SmbArgs),
 }
 
@@ -129,11 +365,26 @@ impl Args {
         let matches = args().get_matches();
 
         match matches.subcommand_name().unwrap() {
+            DIFF_COM => {
+                return Self::Diff(DiffArgs::parse_args(
+                    matches.subcommand_matches(DIFF_COM).unwrap(),
+                ))
+            }
             HTTP_COM => {
                 return Self::Http(HttpArgs::parse_args(
                     matches.subcommand_matches(HTTP_COM).unwrap(),
                 ))
             }
+            PARSE_COM => {
+                return Self::Parse(ParseArgs::parse_args(
+                    matches.subcommand_matches(PARSE_COM).unwrap(),
+                ))
+            }
+            PCAP_COM => {
+                return Self::Pcap(PcapArgs::parse_args(
+                    matches.subcommand_matches(PCAP_COM).unwrap(),
+                ))
+            }
             SMB_COM => {
                 return Self::Smb(SmbArgs::parse_args(
                     matches.subcommand_matches(SMB_COM).unwrap(),
@@ -144,8 +395,37 @@ impl Args {
     }
 }
 
+pub struct DiffArgs {
+    pub db: Option<String>,
+    pub new: Option<String>,
+    pub old: Option<String>,
+    pub verbosity: usize,
+}
+
+impl DiffArgs {
+    fn parse_args(matches: &ArgMatches) -> Self {
+        return Self {
+            db: matches.value_of("db").map(|s| s.to_string()),
+            new: matches.value_of("new").map(|s| s.to_string()),
+            old: matches.value_of("old").map(|s| s.to_string()),
+            verbosity: matches.occurrences_of("verbosity") as usize,
+        };
+    }
+}
+
 pub struct HttpArgs {
-    pub json: Option<String>,
+    pub all_ips: bool,
+    pub discover: bool,
+    pub dns_check: bool,
+    pub dns_search: Vec<String>,
+    pub dns_servers: Vec<String>,
+    pub dns_tcp: bool,
+    pub exclude: Vec<String>,
+    pub exclude_files: Vec<String>,
+    pub max_depth: usize,
+    pub output: OutputArgs,
+    pub recursive: bool,
+    pub scope: Vec<String>,
     pub timeout: Duration,
     pub urls: Vec<String>,
     pub verbosity: usize,
@@ -155,7 +435,18 @@ pub struct HttpArgs {
 impl HttpArgs {
     fn parse_args(matches: &ArgMatches) -> Self {
         return Self {
-            json: parse_json(matches),
+            all_ips: matches.is_present("all-ips"),
+            discover: matches.is_present("discover"),
+            dns_check: matches.is_present("dns-check"),
+            dns_search: parse_vec_strings(matches, "dns-search"),
+            dns_servers: parse_vec_strings(matches, "dns-server"),
+            dns_tcp: matches.is_present("dns-tcp"),
+            exclude: parse_vec_strings(matches, "exclude"),
+            exclude_files: parse_vec_strings(matches, "exclude-file"),
+            max_depth: parse_max_depth(matches),
+            output: OutputArgs::parse_args(matches),
+            recursive: matches.is_present("recursive"),
+            scope: parse_vec_strings(matches, "scope"),
             urls: parse_urls(&matches),
             timeout: parse_timeout(&matches),
             verbosity: matches.occurrences_of("verbosity") as usize,
@@ -164,8 +455,51 @@ impl HttpArgs {
     }
 }
 
+pub struct ParseArgs {
+    pub output: OutputArgs,
+    pub messages: Vec<String>,
+    pub verbosity: usize,
+}
+
+impl ParseArgs {
+    fn parse_args(matches: &ArgMatches) -> Self {
+        return Self {
+            output: OutputArgs::parse_args(matches),
+            messages: parse_vec_strings(matches, "message"),
+            verbosity: matches.occurrences_of("verbosity") as usize,
+        };
+    }
+}
+
+pub struct PcapArgs {
+    pub files: Vec<String>,
+    pub output: OutputArgs,
+    pub verbosity: usize,
+}
+
+impl PcapArgs {
+    fn parse_args(matches: &ArgMatches) -> Self {
+        return Self {
+            files: parse_vec_strings(matches, "file"),
+            output: OutputArgs::parse_args(matches),
+            verbosity: matches.occurrences_of("verbosity") as usize,
+        };
+    }
+}
+
 pub struct SmbArgs {
-    pub json: Option<String>,
+    pub all_ips: bool,
+    pub discover: bool,
+    pub dns_check: bool,
+    pub dns_search: Vec<String>,
+    pub dns_servers: Vec<String>,
+    pub dns_tcp: bool,
+    pub exclude: Vec<String>,
+    pub exclude_files: Vec<String>,
+    pub max_depth: usize,
+    pub output: OutputArgs,
+    pub recursive: bool,
+    pub scope: Vec<String>,
     pub targets: Vec<String>,
     pub timeout: Duration,
     pub verbosity: usize,
@@ -175,7 +509,18 @@ pub struct SmbArgs {
 impl SmbArgs {
     fn parse_args(matches: &ArgMatches) -> Self {
         return Self {
-            json: parse_json(matches),
+            all_ips: matches.is_present("all-ips"),
+            discover: matches.is_present("discover"),
+            dns_check: matches.is_present("dns-check"),
+            dns_search: parse_vec_strings(matches, "dns-search"),
+            dns_servers: parse_vec_strings(matches, "dns-server"),
+            dns_tcp: matches.is_present("dns-tcp"),
+            exclude: parse_vec_strings(matches, "exclude"),
+            exclude_files: parse_vec_strings(matches, "exclude-file"),
+            max_depth: parse_max_depth(matches),
+            output: OutputArgs::parse_args(matches),
+            recursive: matches.is_present("recursive"),
+            scope: parse_vec_strings(matches, "scope"),
             targets: parse_targets(matches),
             timeout: parse_timeout(matches),
             verbosity: matches.occurrences_of("verbosity") as usize,
@@ -184,10 +529,54 @@ impl SmbArgs {
     }
 }
 
+pub struct OutputArgs {
+    pub bloodhound: Option<String>,
+    pub columns: Option<Vec<String>>,
+    pub csv: Option<String>,
+    pub db: Option<String>,
+    pub format: Option<String>,
+    pub full: bool,
+    pub hosts: Option<String>,
+    pub html: Option<String>,
+    pub json: Option<String>,
+    pub jsonl: Option<String>,
+    pub nmap: Option<String>,
+    pub summary: bool,
+    pub tsv: Option<String>,
+    pub zone: Option<String>,
+}
+
+impl OutputArgs {
+    fn parse_args(matches: &ArgMatches) -> Self {
+        return Self {
+            bloodhound: matches.value_of("bloodhound").map(|s| s.to_string()),
+            columns: matches
+                .value_of("columns")
+                .map(|s| s.split(",").map(|c| c.trim().to_string()).collect()),
+            csv: matches.value_of("csv").map(|s| s.to_string()),
+            db: matches.value_of("db").map(|s| s.to_string()),
+            format: matches.value_of("format").map(|s| s.to_string()),
+            full: matches.is_present("full"),
+            hosts: matches.value_of("hosts").map(|s| s.to_string()),
+            html: matches.value_of("html").map(|s| s.to_string()),
+            json: parse_json(matches),
+            jsonl: matches.value_of("jsonl").map(|s| s.to_string()),
+            nmap: matches.value_of("nmap").map(|s| s.to_string()),
+            summary: matches.is_present("summary"),
+            tsv: matches.value_of("tsv").map(|s| s.to_string()),
+            zone: matches.value_of("zone").map(|s| s.to_string()),
+        };
+    }
+}
+
 fn parse_urls(matches: &ArgMatches) -> Vec<String> {
     return parse_vec_strings(matches, "url");
 }
 
+fn parse_max_depth(matches: &ArgMatches) -> usize {
+    return matches.value_of("max-depth").unwrap().parse().unwrap();
+}
+
 fn parse_workers(matches: &ArgMatches) -> usize {
     return matches.value_of("workers").unwrap().parse().unwrap();
 }
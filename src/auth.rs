@@ -1,6 +1,20 @@
+use crate::error::Error;
 use ntlm::flags as ntflag;
-use ntlm::{ChallengeMsg, NegotiateMsg, Version};
+use ntlm::{AuthenticateMsg, ChallengeMsg, NegotiateMsg, NtlmAuth, Version};
 use spnego::{ntlmssp_oid, NegToken};
+use std::convert::TryInto;
+
+pub const NTLMSSP_SIGNATURE: &'static [u8] = b"NTLMSSP\0";
+
+const NTLM_MSG_NEGOTIATE: u32 = 0x00000001;
+pub const NTLM_MSG_CHALLENGE: u32 = 0x00000002;
+const NTLM_MSG_AUTHENTICATE: u32 = 0x00000003;
+
+/// HTTP authentication schemes that transport NTLM messages.
+const NTLM_SCHEMES: &'static [&'static str] = &["NTLM", "Negotiate"];
+
+/// Size of the NTLMv1 response, any other size belongs to NTLMv2.
+const NTLMV1_RESPONSE_SIZE: u16 = 24;
 
 pub fn new_ntlm_negotiate() -> NegotiateMsg {
     let mut ntlm_neg = NegotiateMsg::default();
@@ -32,21 +46,334 @@ pub fn new_spnego_init2() -> NegToken {
     return NegToken::Init2(init2);
 }
 
-pub fn extract_ntlm_challenge(raw: &[u8]) -> Result<ChallengeMsg, String> {
+pub fn extract_ntlm_challenge(raw: &[u8]) -> Result<ChallengeMsg, Error> {
     let spnego_msg = NegToken::parse(raw)
-        .map_err(|_| format!("Error parsing spnego response"))?;
+        .map_err(|_| Error::Parse(format!("Error parsing spnego response")))?;
 
     let spnego_resp = match spnego_msg {
         spnego::NegToken::Resp(resp) => resp,
-        _ => return Err(format!("Unexpected spnego response")),
+        _ => {
+            return Err(Error::Protocol(format!("Unexpected spnego response")))
+        }
     };
 
     let raw_ntlm_challenge = spnego_resp
         .response_token
-        .ok_or(format!("No NTLM challenge in SMB2 response"))?;
+        .ok_or(Error::NoNtlm(format!("No NTLM challenge in SMB2 response")))?;
 
     let ntlm_challenge = ntlm::ChallengeMsg::parse(&raw_ntlm_challenge)
-        .map_err(|_| format!("Error parsing NTLM challenge"))?;
+        .map_err(|_| Error::Parse(format!("Error parsing NTLM challenge")))?;
 
     return Ok(ntlm_challenge);
 }
+
+pub enum NtlmMsg {
+    Negotiate(NegotiateMsg),
+    Challenge(ChallengeMsg),
+    Authenticate(AuthenticateMsg),
+}
+
+/// Decodes a NTLM message encoded in base64 or hex. The message can be
+/// wrapped in SPNEGO and be given as the value of an HTTP authentication
+/// header (WWW-Authenticate/Authorization), including the header name.
+pub fn decode_ntlm_msg(input: &str) -> Result<NtlmMsg, String> {
+    let raw = decode_blob(input)?;
+
+    if raw.starts_with(NTLMSSP_SIGNATURE) {
+        return parse_ntlm_msg(&raw);
+    }
+
+    let spnego_msg = NegToken::parse(&raw)
+        .map_err(|_| format!("Error parsing NTLM or SPNEGO message"))?;
+
+    let raw_ntlm = match spnego_msg {
+        NegToken::Init2(init2) => init2.mech_token,
+        NegToken::Resp(resp) => resp.response_token,
+    }
+    .ok_or(format!("No NTLM message in SPNEGO token"))?;
+
+    return parse_ntlm_msg(&raw_ntlm);
+}
+
+/// Retrieves the bytes of a blob encoded in hex or base64. In case of being
+/// an HTTP header, the header name is discarded and the token of the first
+/// NTLM or Negotiate challenge is taken.
+fn decode_blob(input: &str) -> Result<Vec<u8>, String> {
+    let blob = find_token(input).ok_or(format!("No NTLM message found"))?;
+
+    if blob.len() % 2 == 0 && blob.chars().all(|c| c.is_ascii_hexdigit()) {
+        return hex::decode(blob)
+            .map_err(|e| format!("Error decoding hex: {}", e));
+    }
+
+    return base64::decode(blob)
+        .map_err(|e| format!("Error decoding base64: {}", e));
+}
+
+/// Returns the token of the input, which can be a raw blob or the value of
+/// an authentication header with several challenges, like
+/// `WWW-Authenticate: NTLM <token>, Negotiate`.
+fn find_token(input: &str) -> Option<&str> {
+    let input = input.trim();
+    let value = match input.split_once(':') {
+        Some((name, value)) if is_header_name(name) => value,
+        _ => input,
+    };
+
+    let mut raw = None;
+    for challenge in value.split(',') {
+        let mut words = challenge.split_whitespace();
+        match (words.next(), words.next()) {
+            (Some(scheme), Some(token)) if is_ntlm_scheme(scheme) => {
+                return Some(token);
+            }
+            // a scheme without token, like "Negotiate" in a list
+            (Some(word), None) if is_ntlm_scheme(word) => {}
+            (Some(word), None) if raw.is_none() => raw = Some(word),
+            _ => {}
+        }
+    }
+
+    return raw;
+}
+
+fn is_header_name(name: &str) -> bool {
+    return name.len() != 0
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-');
+}
+
+fn is_ntlm_scheme(scheme: &str) -> bool {
+    return NTLM_SCHEMES.iter().any(|s| s.eq_ignore_ascii_case(scheme));
+}
+
+pub fn parse_ntlm_msg(raw: &[u8]) -> Result<NtlmMsg, String> {
+    let msg_type = raw
+        .get(8..12)
+        .map(|t| u32::from_le_bytes(t.try_into().unwrap()))
+        .ok_or(format!("Error parsing NTLM message: no enough data"))?;
+
+    match msg_type {
+        NTLM_MSG_NEGOTIATE => {
+            let msg = NegotiateMsg::parse(raw).map_err(|e| {
+                format!("Error parsing NTLM negotiate message: {}", e)
+            })?;
+            return Ok(NtlmMsg::Negotiate(msg));
+        }
+        NTLM_MSG_CHALLENGE => {
+            let msg = ChallengeMsg::parse(raw).map_err(|e| {
+                format!("Error parsing NTLM challenge message: {}", e)
+            })?;
+            return Ok(NtlmMsg::Challenge(msg));
+        }
+        NTLM_MSG_AUTHENTICATE => {
+            let msg = AuthenticateMsg::parse(raw, ntlm_auth_version(raw))
+                .map_err(|e| {
+                    format!("Error parsing NTLM authenticate message: {}", e)
+                })?;
+            return Ok(NtlmMsg::Authenticate(msg));
+        }
+        _ => {
+            return Err(format!("Unknown NTLM message type {}", msg_type));
+        }
+    }
+}
+
+/// Guess the NTLM version used in an authenticate message by
+/// checking the size of the NTLM response.
+fn ntlm_auth_version(raw: &[u8]) -> NtlmAuth {
+    let nt_resp_len = raw
+        .get(20..22)
+        .map(|l| u16::from_le_bytes(l.try_into().unwrap()));
+
+    match nt_resp_len {
+        Some(NTLMV1_RESPONSE_SIZE) => NtlmAuth::V1,
+        _ => NtlmAuth::V2,
+    }
+}
+
+const NEG_FLAGS_NAMES: &'static [(u32, &'static str)] = &[
+    (ntflag::NTLM_NEG_UNICODE, "NTLMSSP_NEGOTIATE_UNICODE"),
+    (ntflag::NTLM_NEG_OEM, "NTLMSSP_NEGOTIATE_OEM"),
+    (ntflag::NTLM_REQUEST_TARGET, "NTLMSSP_REQUEST_TARGET"),
+    (ntflag::NTLM_NEG_R10, "NTLMSSP_R10"),
+    (ntflag::NTLM_NEG_SIGN, "NTLMSSP_NEGOTIATE_SIGN"),
+    (ntflag::NTLM_NEG_SEAL, "NTLMSSP_NEGOTIATE_SEAL"),
+    (ntflag::NTLM_NEG_DATAGRAM, "NTLMSSP_NEGOTIATE_DATAGRAM"),
+    (ntflag::NTLM_NEG_LM_KEY, "NTLMSSP_NEGOTIATE_LM_KEY"),
+    (ntflag::NTLM_NEG_R9, "NTLMSSP_R9"),
+    (ntflag::NTLM_NEG_NTLM, "NTLMSSP_NEGOTIATE_NTLM"),
+    (ntflag::NTLM_NEG_R8, "NTLMSSP_R8"),
+    (ntflag::NTLM_NEG_ANONYMOUS, "NTLMSSP_ANONYMOUS"),
+    (
+        ntflag::NTLM_NEG_OEM_DOMAIN_SUPPLIED,
+        "NTLMSSP_NEGOTIATE_OEM_DOMAIN_SUPPLIED",
+    ),
+    (
+        ntflag::NTLM_NEG_OEM_WORKSTATION_SUPPLIED,
+        "NTLMSSP_NEGOTIATE_OEM_WORKSTATION_SUPPLIED",
+    ),
+    (ntflag::NTLM_NEG_R7, "NTLMSSP_R7"),
+    (
+        ntflag::NTLM_NEG_ALWAYS_SIGN,
+        "NTLMSSP_NEGOTIATE_ALWAYS_SIGN",
+    ),
+    (
+        ntflag::NTLM_NEG_TARGET_TYPE_DOMAIN,
+        "NTLMSSP_TARGET_TYPE_DOMAIN",
+    ),
+    (
+        ntflag::NTLM_NEG_TARGET_TYPE_SERVER,
+        "NTLMSSP_TARGET_TYPE_SERVER",
+    ),
+    (ntflag::NTLM_NEG_R6, "NTLMSSP_R6"),
+    (
+        ntflag::NTLM_NEG_EXTENDED_SECURITY,
+        "NTLMSSP_NEGOTIATE_EXTENDED_SESSIONSECURITY",
+    ),
+    (ntflag::NTLM_NEG_IDENTITY, "NTLMSSP_NEGOTIATE_IDENTIFY"),
+    (ntflag::NTLM_NEG_R5, "NTLMSSP_R5"),
+    (
+        ntflag::NTLM_REQUEST_NON_NT_SESSION_KEY,
+        "NTLMSSP_REQUEST_NON_NT_SESSION_KEY",
+    ),
+    (
+        ntflag::NTLM_NEG_TARGET_INFO,
+        "NTLMSSP_NEGOTIATE_TARGET_INFO",
+    ),
+    (ntflag::NTLM_NEG_R4, "NTLMSSP_R4"),
+    (ntflag::NTLM_NEG_VERSION, "NTLMSSP_NEGOTIATE_VERSION"),
+    (ntflag::NTLM_NEG_R3, "NTLMSSP_R3"),
+    (ntflag::NTLM_NEG_R2, "NTLMSSP_R2"),
+    (ntflag::NTLM_NEG_R1, "NTLMSSP_R1"),
+    (ntflag::NTLM_NEG_128, "NTLMSSP_NEGOTIATE_128"),
+    (ntflag::NTLM_NEG_KEY_EXCH, "NTLMSSP_NEGOTIATE_KEY_EXCH"),
+    (ntflag::NTLM_NEG_56, "NTLMSSP_NEGOTIATE_56"),
+];
+
+/// Flags of the MsvAvFlags AV pair, defined in [MS-NLMP] section 2.2.2.1.
+const AV_FLAGS_NAMES: &'static [(u32, &'static str)] = &[
+    (0x00000001, "CONSTRAINED_AUTHENTICATION"),
+    (0x00000002, "MIC_PROVIDED"),
+    (0x00000004, "UNTRUSTED_SPN_SOURCE"),
+];
+
+/// Returns the names of the negotiate flags set in a NTLM message.
+pub fn neg_flags_names(flags: u32) -> Vec<&'static str> {
+    return flags_names(flags, NEG_FLAGS_NAMES);
+}
+
+/// Returns the names of the flags set in the MsvAvFlags AV pair.
+pub fn av_flags_names(flags: u32) -> Vec<&'static str> {
+    return flags_names(flags, AV_FLAGS_NAMES);
+}
+
+fn flags_names(flags: u32, names: &[(u32, &'static str)]) -> Vec<&'static str> {
+    return names
+        .iter()
+        .filter(|(flag, _)| (flags & flag) != 0)
+        .map(|(_, name)| *name)
+        .collect();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ntlm::AvPair;
+    use spnego::NegTokenResp;
+
+    fn challenge_msg() -> ChallengeMsg {
+        let mut challenge = ChallengeMsg::default();
+        // the version is required by build to place the payload
+        challenge.flags = ntflag::NTLM_NEG_UNICODE
+            | ntflag::NTLM_NEG_TARGET_INFO
+            | ntflag::NTLM_NEG_VERSION;
+        challenge.version = Some(Version::windows7_7601());
+        challenge.server_challenge = [1, 2, 3, 4, 5, 6, 7, 8];
+        challenge.target_info = vec![
+            AvPair::NbComputerName("WS01".to_string()),
+            AvPair::DnsComputerName("ws01.contoso.local".to_string()),
+            AvPair::EOL,
+        ]
+        .into();
+        return challenge;
+    }
+
+    fn decoded_challenge(input: &str) -> ChallengeMsg {
+        match decode_ntlm_msg(input) {
+            Ok(NtlmMsg::Challenge(ch)) => ch,
+            Ok(_) => panic!("Unexpected message type in '{}'", input),
+            Err(err) => panic!("Error decoding '{}': {}", input, err),
+        }
+    }
+
+    #[test]
+    fn decode_raw_base64_and_hex() {
+        let raw = challenge_msg().build();
+
+        let ch = decoded_challenge(&base64::encode(&raw));
+        assert_eq!(ch.server_challenge, [1, 2, 3, 4, 5, 6, 7, 8]);
+
+        let ch = decoded_challenge(&hex::encode(&raw));
+        assert_eq!(
+            ch.dns_computer_name().map(|s| s.as_str()),
+            Some("ws01.contoso.local")
+        );
+    }
+
+    #[test]
+    fn decode_header_with_several_challenges() {
+        let b64 = base64::encode(challenge_msg().build());
+
+        for header in [
+            format!("WWW-Authenticate: NTLM {}, Negotiate", b64),
+            format!("WWW-Authenticate: Negotiate, NTLM {}", b64),
+            format!("www-authenticate: Basic realm=\"x\", ntlm {}", b64),
+            format!("NTLM {}", b64),
+        ]
+        .iter()
+        {
+            let ch = decoded_challenge(header);
+            assert_eq!(ch.nb_computer_name().map(|s| s.as_str()), Some("WS01"));
+        }
+    }
+
+    #[test]
+    fn decode_header_without_token() {
+        assert!(decode_ntlm_msg("WWW-Authenticate: Negotiate, NTLM").is_err());
+    }
+
+    #[test]
+    fn decode_spnego_tokens() {
+        let init2 = base64::encode(new_spnego_init2().build());
+        match decode_ntlm_msg(&format!("Authorization: Negotiate {}", init2)) {
+            Ok(NtlmMsg::Negotiate(neg)) => {
+                assert_eq!(neg.flags, new_ntlm_negotiate().flags)
+            }
+            _ => panic!("SPNEGO init2 must contain a negotiate message"),
+        }
+
+        let mut resp = NegTokenResp::default();
+        resp.response_token = Some(challenge_msg().build());
+        let resp = base64::encode(NegToken::Resp(resp).build());
+
+        let ch =
+            decoded_challenge(&format!("WWW-Authenticate: Negotiate {}", resp));
+        assert_eq!(ch.server_challenge, [1, 2, 3, 4, 5, 6, 7, 8]);
+    }
+
+    #[test]
+    fn decode_authenticate() {
+        let mut auth = AuthenticateMsg::default();
+        auth.domain_name = "CONTOSO".to_string();
+        auth.username = "alice".to_string();
+        auth.workstation = "WS01".to_string();
+
+        match decode_ntlm_msg(&base64::encode(auth.build())) {
+            Ok(NtlmMsg::Authenticate(decoded)) => {
+                assert_eq!(decoded.username, "alice");
+                assert_eq!(decoded.domain_name, "CONTOSO");
+            }
+            _ => panic!("Authenticate message not decoded"),
+        }
+    }
+}
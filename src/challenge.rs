@@ -1,21 +1,148 @@
+use crate::dns::DnsCheck;
+use crate::error::Error;
+use chrono::Duration;
+use ntlm::time::NtlmTime;
 use ntlm::ChallengeMsg;
 use std::fmt;
-use std::net::IpAddr;
+use std::net::{IpAddr, SocketAddr};
+use url::Url;
 
 pub struct Challenge {
     pub target: ChallengeTarget,
     pub challenge: ChallengeMsg,
+
+    /// Address of the server that sent the challenge.
+    pub address: Option<SocketAddr>,
+
+    /// Application protocol used to transport the challenge.
+    pub protocol: Option<Protocol>,
+
+    /// Local time when the challenge was received from the server. It is
+    /// unknown for challenges that were not retrieved from the network.
+    pub received: Option<NtlmTime>,
+
+    /// Time indicated by the Date header of the HTTP response.
+    pub http_date: Option<NtlmTime>,
+
+    /// Comparison of the DNS records with the names of the challenge.
+    pub dns: Option<DnsCheck>,
+
+    /// Hostnames found while retrieving the challenge, in HTTP redirects or
+    /// the TLS certificate, which could belong to other hosts.
+    pub seen_names: Vec<String>,
 }
 
 impl Challenge {
     pub fn new(target: ChallengeTarget, challenge: ChallengeMsg) -> Self {
-        return Self { target, challenge };
+        return Self {
+            target,
+            challenge,
+            address: None,
+            protocol: None,
+            received: Some(ntlm::time::now()),
+            http_date: None,
+            dns: None,
+            seen_names: Vec::new(),
+        };
+    }
+
+    /// Returns the IP of the server that sent the challenge.
+    pub fn ip(&self) -> Option<IpAddr> {
+        if let Some(address) = &self.address {
+            return Some(address.ip());
+        }
+
+        match &self.target {
+            ChallengeTarget::Host(h) => Some(h.ip),
+            _ => None,
+        }
+    }
+
+    /// Returns the hostname used to reach the server, if any.
+    pub fn hostname(&self) -> Option<String> {
+        match &self.target {
+            ChallengeTarget::Host(h) => h.names.first().cloned(),
+            ChallengeTarget::Url(u) => {
+                Url::parse(u).ok()?.domain().map(|d| d.to_string())
+            }
+            ChallengeTarget::Input(_) => None,
+        }
+    }
+
+    /// Returns all the hostnames of the server given as targets, which
+    /// resolve to its IP.
+    pub fn hostnames(&self) -> Vec<String> {
+        match &self.target {
+            ChallengeTarget::Host(h) => h.names.clone(),
+            _ => self.hostname().into_iter().collect(),
+        }
+    }
+
+    /// Returns the port of the server that sent the challenge.
+    pub fn port(&self) -> Option<u16> {
+        if let Some(address) = &self.address {
+            return Some(address.port());
+        }
+
+        match &self.target {
+            ChallengeTarget::Url(u) => {
+                Url::parse(u).ok()?.port_or_known_default()
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the server time indicated in the MsvAvTimestamp AV pair.
+    pub fn server_time(&self) -> Option<&NtlmTime> {
+        return self.challenge.timestamp();
+    }
+
+    /// Returns the difference between the server time and the local time
+    /// when the challenge was received. Positive if server is ahead.
+    pub fn skew(&self) -> Option<Duration> {
+        return Some(*self.server_time()? - self.received?);
+    }
+
+    /// Returns the difference between the HTTP Date and the local time
+    /// when the challenge was received. Positive if server is ahead.
+    pub fn http_skew(&self) -> Option<Duration> {
+        return Some(self.http_date? - self.received?);
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Protocol {
+    Http,
+    Smb,
+}
+
+impl fmt::Display for Protocol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Http => write!(f, "http"),
+            Self::Smb => write!(f, "smb"),
+        }
+    }
+}
+
+/// A target from which the NTLM challenge couldn't be retrieved.
+pub struct Failure {
+    pub target: String,
+    pub error: Error,
+}
+
+impl Failure {
+    pub fn new(target: String, error: Error) -> Self {
+        return Self { target, error };
     }
 }
 
 pub enum ChallengeTarget {
     Host(Host),
     Url(String),
+
+    /// The position of a message given as input to be parsed.
+    Input(usize),
 }
 
 impl fmt::Display for ChallengeTarget {
@@ -23,6 +150,7 @@ impl fmt::Display for ChallengeTarget {
         match &self {
             Self::Host(h) => write!(f, "{}", h),
             Self::Url(u) => write!(f, "{}", u),
+            Self::Input(i) => write!(f, "input#{}", i),
         }
     }
 }
@@ -41,20 +169,74 @@ impl From<&str> for ChallengeTarget {
 
 pub struct Host {
     pub ip: IpAddr,
-    pub name: Option<String>,
+
+    /// Hostnames given as targets that resolve to the IP.
+    pub names: Vec<String>,
 }
 
 impl Host {
-    pub fn new(ip: IpAddr, name: Option<String>) -> Self {
-        return Self { ip, name };
+    pub fn new(ip: IpAddr, names: Vec<String>) -> Self {
+        return Self { ip, names };
     }
 }
 
 impl fmt::Display for Host {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match &self.name {
-            Some(name) => write!(f, "{}/{}", self.ip, name),
-            None => write!(f, "{}", self.ip),
+        match self.names.len() {
+            0 => write!(f, "{}", self.ip),
+            _ => write!(f, "{}/{}", self.ip, self.names.join(",")),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ntlm::time::new_time;
+    use ntlm::AvPair;
+
+    fn received() -> NtlmTime {
+        return new_time(2021, 6, 1, 12, 0, 0, 0);
+    }
+
+    /// Returns a challenge received at `received()` whose MsvAvTimestamp is
+    /// `server_time`.
+    fn challenge_at(server_time: Option<NtlmTime>) -> Challenge {
+        let mut nt_ch = ChallengeMsg::default();
+        if let Some(time) = server_time {
+            nt_ch.target_info =
+                vec![AvPair::Timestamp(time), AvPair::EOL].into();
+        }
+
+        let target = ChallengeTarget::Url("http://ws01/".to_string());
+        let mut ch = Challenge::new(target, nt_ch);
+        ch.received = Some(received());
+        return ch;
+    }
+
+    #[test]
+    fn skew_of_server_ahead_and_behind() {
+        let ch =
+            challenge_at(Some(new_time(2021, 6, 1, 12, 0, 2, 500_000_000)));
+        assert_eq!(ch.skew(), Some(Duration::milliseconds(2500)));
+
+        let ch = challenge_at(Some(new_time(2021, 6, 1, 11, 50, 0, 0)));
+        assert_eq!(ch.skew(), Some(Duration::minutes(-10)));
+    }
+
+    #[test]
+    fn skew_without_timestamp() {
+        let ch = challenge_at(None);
+        assert_eq!(ch.server_time(), None);
+        assert_eq!(ch.skew(), None);
+    }
+
+    #[test]
+    fn http_skew_of_date_header() {
+        let mut ch = challenge_at(None);
+        assert_eq!(ch.http_skew(), None);
+
+        ch.http_date = Some(new_time(2021, 6, 1, 11, 59, 59, 0));
+        assert_eq!(ch.http_skew(), Some(Duration::seconds(-1)));
+    }
+}
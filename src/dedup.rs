@@ -0,0 +1,328 @@
+use crate::challenge::Host;
+use crate::error::Error;
+use crate::range::{Exclusions, IpRange, Scope};
+use crate::smb::{split_host_port, SmbOptions};
+use log::info;
+use std::collections::{HashMap, HashSet};
+use std::net::IpAddr;
+
+/// Number of targets that are deduplicated together. The targets are read
+/// in batches so they aren't kept in memory, and the hostnames of a batch
+/// are resolved in parallel.
+pub const DEDUP_BATCH: usize = 1024;
+
+/// Removes the duplicated SMB targets after expanding the ranges and
+/// resolving the hostnames, so each IP and port is only scanned once. The
+/// hostnames that resolve to the same IP are merged in the same host.
+pub struct Dedup {
+    options: SmbOptions,
+
+    /// Ranges already scanned, which are kept instead of their IPs to not
+    /// use memory with big ranges.
+    ranges: Vec<IpRange>,
+
+    /// Hosts already scanned, out of the ranges. It grows with every IP or
+    /// hostname given as a single target, by about 40 bytes each, so a
+    /// million of them use about 40 MB.
+    scanned: HashSet<(IpAddr, u16)>,
+}
+
+impl Dedup {
+    pub fn new(options: &SmbOptions) -> Self {
+        return Self {
+            options: options.clone(),
+            ranges: Vec::new(),
+            scanned: HashSet::new(),
+        };
+    }
+
+    /// Passes each host of the targets to `dispatch` with its port, unless
+    /// it was already scanned or is excluded. Hostnames that can't be
+    /// resolved are passed to `fail`. If a scope is given, only its IPs
+    /// are scanned, unless the hostname is in scope.
+    pub fn dispatch<D, F>(
+        &mut self,
+        targets: Vec<String>,
+        exclusions: &Exclusions,
+        scope: Option<&Scope>,
+        mut dispatch: D,
+        mut fail: F,
+    ) where
+        D: FnMut(Host, u16),
+        F: FnMut(String, Error),
+    {
+        let in_scope = |ip: &IpAddr| match scope {
+            Some(scope) => scope.contains_ip(ip),
+            None => true,
+        };
+
+        let mut ranges = Vec::new();
+        let mut hosts = Hosts::default();
+        let mut hostnames = Vec::new();
+
+        for target in targets {
+            match IpRange::parse(&target) {
+                Some(IpRange::Ip(ip)) => hosts.add(ip, self.options.port, None),
+                Some(range) => ranges.push(range),
+                None => {
+                    let (host, port) =
+                        split_host_port(&target, self.options.port);
+                    match host.parse::<IpAddr>() {
+                        Ok(ip) => hosts.add(ip, port, None),
+                        Err(_) => hostnames.push((target, host, port)),
+                    }
+                }
+            }
+        }
+
+        let names: Vec<String> =
+            hostnames.iter().map(|(_, host, _)| host.clone()).collect();
+        let results = self.options.resolver.resolve_hostnames(&names);
+        for ((target, host, port), result) in hostnames.into_iter().zip(results)
+        {
+            let result = match (result, scope) {
+                (Ok(ips), Some(scope)) => Ok(scope.filter_ips(&host, ips)),
+                (result, _) => result,
+            };
+
+            match result {
+                Ok(ips) if ips.len() == 0 => {
+                    info!("Skipping {}, out of scope", target);
+                }
+                Ok(ips) if self.options.all_ips => {
+                    for ip in ips {
+                        hosts.add(ip, port, Some(&host));
+                    }
+                }
+                Ok(ips) => hosts.add(ips[0], port, Some(&host)),
+                Err(err) => fail(target, err),
+            }
+        }
+
+        // the IPs of the ranges are generated, so the names of other
+        // targets are added when the IP appears. Each range is recorded once
+        // its IPs are dispatched, and the lists are split in their members
+        // so a member doesn't dispatch the IPs of the previous ones.
+        for range in ranges.into_iter().flat_map(split_range) {
+            for ip in range.clone().ips() {
+                if exclusions.contains_ip(&ip) {
+                    info!("Excluding {}", ip);
+                    continue;
+                }
+
+                if !in_scope(&ip) {
+                    continue;
+                }
+
+                let port = self.options.port;
+                if self.is_scanned(&ip, port) {
+                    continue;
+                }
+
+                let names = hosts.names.remove(&(ip, port)).unwrap_or_default();
+                dispatch(Host::new(ip, names), port);
+            }
+            self.ranges.push(range);
+        }
+
+        for (ip, port) in hosts.order {
+            // hosts already dispatched in the ranges are removed
+            let names = match hosts.names.remove(&(ip, port)) {
+                Some(names) => names,
+                None => continue,
+            };
+
+            if exclusions.contains_ip(&ip) {
+                info!("Excluding {}", Host::new(ip, names));
+                continue;
+            }
+
+            // the IPs of hostnames were already checked
+            if names.len() == 0 && !in_scope(&ip) {
+                info!("Skipping {}, out of scope", ip);
+                continue;
+            }
+
+            if self.is_scanned(&ip, port) {
+                info!("Skipping {}, already scanned", Host::new(ip, names));
+                continue;
+            }
+
+            self.scanned.insert((ip, port));
+            dispatch(Host::new(ip, names), port);
+        }
+    }
+
+    fn is_scanned(&self, ip: &IpAddr, port: u16) -> bool {
+        if self.scanned.contains(&(*ip, port)) {
+            return true;
+        }
+
+        return port == self.options.port
+            && self.ranges.iter().any(|r| r.contains(ip));
+    }
+}
+
+/// Returns the ranges of the IPs that are generated from the range, to be
+/// recorded when they are dispatched: the members of the lists, which don't
+/// repeat IPs by themselves, and the hosts of the networks, since their
+/// network and broadcast IPs are not generated.
+fn split_range(range: IpRange) -> Vec<IpRange> {
+    match range {
+        IpRange::List(ranges) => {
+            ranges.into_iter().flat_map(split_range).collect()
+        }
+        IpRange::Net(net) => {
+            let mut hosts = net.hosts();
+            match (hosts.next(), hosts.next_back()) {
+                (Some(first), Some(last)) => vec![IpRange::Span(first, last)],
+                (Some(ip), None) => vec![IpRange::Ip(ip)],
+                _ => Vec::new(),
+            }
+        }
+        range => vec![range],
+    }
+}
+
+/// Hosts of the targets, with the names of each IP and port, in the order
+/// in which they appear.
+#[derive(Default)]
+struct Hosts {
+    order: Vec<(IpAddr, u16)>,
+    names: HashMap<(IpAddr, u16), Vec<String>>,
+}
+
+impl Hosts {
+    fn add(&mut self, ip: IpAddr, port: u16, name: Option<&str>) {
+        let order = &mut self.order;
+        let names = self.names.entry((ip, port)).or_insert_with(|| {
+            order.push((ip, port));
+            Vec::new()
+        });
+
+        if let Some(name) = name {
+            let name = name.trim_end_matches('.').to_lowercase();
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dns::DnsResolver;
+    use std::time::Duration;
+
+    fn new_dedup() -> Dedup {
+        return Dedup::new(&SmbOptions {
+            timeout: Duration::from_secs(1),
+            port: 445,
+            all_ips: false,
+            resolver: DnsResolver::new(&[], false, &[]).unwrap(),
+        });
+    }
+
+    /// Dispatches the targets, returning the dispatched hosts as strings.
+    fn dispatch(
+        dedup: &mut Dedup,
+        targets: &[&str],
+        scope: Option<&Scope>,
+    ) -> Vec<String> {
+        let exclusions = Exclusions::new(&vec![], &vec![]).unwrap();
+        let mut hosts = Vec::new();
+        dedup.dispatch(
+            targets.iter().map(|t| t.to_string()).collect(),
+            &exclusions,
+            scope,
+            |host, port| hosts.push(format!("{}:{}", host, port)),
+            |target, err| panic!("{}: {}", target, err),
+        );
+        return hosts;
+    }
+
+    #[test]
+    fn dispatch_only_in_scope_ips() {
+        let scope = Scope::new(&["127.0.0.1".to_string()]);
+        let hosts = dispatch(
+            &mut new_dedup(),
+            &["localhost", "127.0.0.5", "127.0.0.0/30"],
+            Some(&scope),
+        );
+        assert_eq!(hosts, vec!["127.0.0.1/localhost:445"]);
+
+        // names in scope can use any IP
+        let scope = Scope::new(&["localhost".to_string()]);
+        let hosts = dispatch(&mut new_dedup(), &["localhost"], Some(&scope));
+        assert_eq!(hosts, vec!["127.0.0.1/localhost:445"]);
+
+        let scope = Scope::new(&["10.0.0.0/8".to_string()]);
+        let hosts = dispatch(&mut new_dedup(), &["localhost"], Some(&scope));
+        assert_eq!(hosts.len(), 0);
+    }
+
+    #[test]
+    fn dispatch_overlapping_ranges_once() {
+        let hosts = dispatch(
+            &mut new_dedup(),
+            &["127.0.0.0/29", "127.0.0.2-10", "127.0.0.1"],
+            None,
+        );
+        let expected: Vec<String> =
+            (1..=10).map(|i| format!("127.0.0.{}:445", i)).collect();
+        assert_eq!(hosts, expected);
+
+        // the members of a list can overlap
+        let hosts = dispatch(
+            &mut new_dedup(),
+            &["127.0.0.0/30,127.0.0.2,127.0.0.1-3"],
+            None,
+        );
+        assert_eq!(
+            hosts,
+            vec!["127.0.0.1:445", "127.0.0.2:445", "127.0.0.3:445"]
+        );
+    }
+
+    #[test]
+    fn merge_hostnames_and_ips() {
+        let mut dedup = new_dedup();
+        let hosts = dispatch(
+            &mut dedup,
+            &["127.0.0.1", "localhost", "127.0.0.0/30"],
+            None,
+        );
+        assert_eq!(hosts, vec!["127.0.0.1/localhost:445", "127.0.0.2:445"]);
+
+        let hosts =
+            dispatch(&mut dedup, &["localhost:139", "127.0.0.1:139"], None);
+        assert_eq!(hosts, vec!["127.0.0.1/localhost:139"]);
+
+        // hosts scanned in previous batches are skipped, but not the
+        // broadcast IP of the network, which wasn't scanned
+        let hosts = dispatch(
+            &mut dedup,
+            &["127.0.0.2", "localhost", "127.0.0.3"],
+            None,
+        );
+        assert_eq!(hosts, vec!["127.0.0.3:445"]);
+    }
+
+    #[test]
+    fn skip_duplicates_across_batches() {
+        let targets: Vec<String> = (0..DEDUP_BATCH + 500)
+            .map(|i| format!("127.0.{}.{}", i / 256, i % 256))
+            .collect();
+        let targets: Vec<&str> = targets.iter().map(|t| t.as_str()).collect();
+
+        // the targets are given twice, in batches as in smb_main
+        let mut dedup = new_dedup();
+        let mut dispatched = 0;
+        let repeated = [targets.clone(), targets.clone()].concat();
+        for batch in repeated.chunks(DEDUP_BATCH) {
+            dispatched += dispatch(&mut dedup, batch, None).len();
+        }
+        assert_eq!(dispatched, targets.len());
+    }
+}
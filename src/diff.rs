@@ -0,0 +1,357 @@
+use log::warn;
+use rusqlite::{params, Connection};
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs::File;
+
+/// Fields of a host compared between scans.
+#[derive(Deserialize)]
+pub struct ScanHost {
+    pub target: String,
+    #[serde(default)]
+    pub ip: Option<String>,
+    #[serde(default)]
+    pub port: Option<u16>,
+    #[serde(default)]
+    pub protocol: Option<String>,
+    #[serde(default)]
+    pub nb_computer: Option<String>,
+    #[serde(default)]
+    pub nb_domain: Option<String>,
+    #[serde(default)]
+    pub dns_computer: Option<String>,
+    #[serde(default)]
+    pub dns_domain: Option<String>,
+    #[serde(default)]
+    pub version: Option<String>,
+}
+
+impl ScanHost {
+    /// Hosts are compared by IP, or by target when the IP is unknown, plus
+    /// the port and protocol, since a host can be scanned in several ports.
+    fn key(&self) -> String {
+        let addr = match &self.ip {
+            Some(ip) if ip.contains(':') => format!("[{}]", ip),
+            Some(ip) => ip.clone(),
+            None => self.target.clone(),
+        };
+
+        let mut key = match self.port {
+            Some(port) => format!("{}:{}", addr, port),
+            None => addr,
+        };
+        if let Some(protocol) =
+            self.protocol.as_deref().filter(|p| !p.is_empty())
+        {
+            key = format!("{}/{}", key, protocol);
+        }
+        return key;
+    }
+
+    fn name(&self) -> Option<&str> {
+        return self.dns_computer.as_deref().or(self.nb_computer.as_deref());
+    }
+
+    fn label(&self) -> String {
+        match self.name() {
+            Some(name) => format!("{} ({})", self.key(), name),
+            None => self.key(),
+        }
+    }
+}
+
+/// Hosts of a scan, indexed by IP, port and protocol.
+pub type Scan = BTreeMap<String, ScanHost>;
+
+/// Indexes the hosts of a scan. If several hosts have the same key, only
+/// the first one is compared, and a warning is shown.
+fn new_scan(path: &str, hosts: Vec<ScanHost>) -> Scan {
+    let mut scan = Scan::new();
+    for host in hosts {
+        let key = host.key();
+        if scan.contains_key(&key) {
+            warn!(
+                "Several results of {} in '{}', only the first one is compared",
+                key, path
+            );
+            continue;
+        }
+        scan.insert(key, host);
+    }
+    return scan;
+}
+
+/// Load the challenges of a JSON file saved with --json, ignoring the
+/// failed targets and the messages of clients.
+pub fn load_json(path: &str) -> Result<Scan, String> {
+    let file = File::open(path)
+        .map_err(|e| format!("Error opening '{}': {}", path, e))?;
+
+    let records: Vec<Value> = serde_json::from_reader(file)
+        .map_err(|e| format!("Error reading JSON data in '{}': {}", path, e))?;
+
+    let mut hosts = Vec::new();
+    for record in records {
+        if record.get("error").is_some() || record.get("message").is_some() {
+            continue;
+        }
+
+        hosts.push(serde_json::from_value(record).map_err(|e| {
+            format!("Error reading JSON data in '{}': {}", path, e)
+        })?);
+    }
+
+    return Ok(new_scan(path, hosts));
+}
+
+/// Returns the IDs of the last two scans of a results database, from the
+/// oldest to the newest.
+pub fn last_db_scans(path: &str) -> Result<(i64, i64), String> {
+    let conn = open_db(path)?;
+    let mut stmt = conn
+        .prepare("SELECT id FROM scans ORDER BY id DESC LIMIT 2")
+        .map_err(|e| db_error(path, e))?;
+    let ids = stmt
+        .query_map([], |row| row.get(0))
+        .and_then(|rows| rows.collect::<Result<Vec<i64>, _>>())
+        .map_err(|e| db_error(path, e))?;
+
+    if ids.len() < 2 {
+        return Err(format!("There are less than 2 scans in '{}'", path));
+    }
+
+    return Ok((ids[1], ids[0]));
+}
+
+/// Load the challenges of a scan saved in a results database. A scan
+/// without results is empty, but an unknown scan ID is an error.
+pub fn load_db_scan(path: &str, scan: i64) -> Result<Scan, String> {
+    let conn = open_db(path)?;
+    let scans: i64 = conn
+        .query_row(
+            "SELECT COUNT(*) FROM scans WHERE id = ?1",
+            params![scan],
+            |row| row.get(0),
+        )
+        .map_err(|e| db_error(path, e))?;
+    if scans == 0 {
+        return Err(format!("Scan {} not found in '{}'", scan, path));
+    }
+
+    let mut stmt = conn
+        .prepare(
+            "SELECT target, ip, port, protocol, nb_computer, nb_domain,
+                dns_computer, dns_domain, version
+            FROM observations WHERE scan = ?1 ORDER BY id",
+        )
+        .map_err(|e| db_error(path, e))?;
+
+    let hosts = stmt
+        .query_map(params![scan], |row| {
+            Ok(ScanHost {
+                target: row.get(0)?,
+                ip: row.get(1)?,
+                port: row.get(2)?,
+                protocol: row.get(3)?,
+                nb_computer: row.get(4)?,
+                nb_domain: row.get(5)?,
+                dns_computer: row.get(6)?,
+                dns_domain: row.get(7)?,
+                version: row.get(8)?,
+            })
+        })
+        .and_then(|rows| rows.collect::<Result<Vec<ScanHost>, _>>())
+        .map_err(|e| db_error(path, e))?;
+
+    return Ok(new_scan(path, hosts));
+}
+
+fn open_db(path: &str) -> Result<Connection, String> {
+    return Connection::open_with_flags(
+        path,
+        rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY,
+    )
+    .map_err(|e| db_error(path, e));
+}
+
+fn db_error(path: &str, err: rusqlite::Error) -> String {
+    return format!("Error reading database '{}': {}", path, err);
+}
+
+/// Print the differences between two scans: new and disappeared hosts,
+/// and changes of names, domains and versions of the same host.
+pub fn print_diff(old: &Scan, new: &Scan) {
+    let mut added = Vec::new();
+    let mut removed = Vec::new();
+    let mut renamed = Vec::new();
+    let mut domain_changed = Vec::new();
+    let mut version_changed = Vec::new();
+
+    for (key, new_host) in new.iter() {
+        let old_host = match old.get(key) {
+            Some(old_host) => old_host,
+            None => {
+                added.push(new_host.label());
+                continue;
+            }
+        };
+
+        if changed(&old_host.dns_computer, &new_host.dns_computer)
+            || changed(&old_host.nb_computer, &new_host.nb_computer)
+        {
+            renamed.push(format!(
+                "{}: {} -> {}",
+                key,
+                old_host.name().unwrap_or("-"),
+                new_host.name().unwrap_or("-")
+            ));
+        }
+
+        if changed(&old_host.dns_domain, &new_host.dns_domain)
+            || changed(&old_host.nb_domain, &new_host.nb_domain)
+        {
+            domain_changed.push(format!(
+                "{}: {} -> {}",
+                new_host.label(),
+                domain_to_string(old_host),
+                domain_to_string(new_host)
+            ));
+        }
+
+        if changed(&old_host.version, &new_host.version) {
+            version_changed.push(format!(
+                "{}: {} -> {}",
+                new_host.label(),
+                old_host.version.as_deref().unwrap_or("-"),
+                new_host.version.as_deref().unwrap_or("-")
+            ));
+        }
+    }
+
+    for (key, old_host) in old.iter() {
+        if !new.contains_key(key) {
+            removed.push(old_host.label());
+        }
+    }
+
+    println!(
+        "Hosts: {} -> {}  New: {}  Disappeared: {}  Renamed: {}  Domain changes: {}  Version changes: {}",
+        old.len(),
+        new.len(),
+        added.len(),
+        removed.len(),
+        renamed.len(),
+        domain_changed.len(),
+        version_changed.len()
+    );
+    print_section("New hosts", &added);
+    print_section("Disappeared hosts", &removed);
+    print_section("Renamed hosts", &renamed);
+    print_section("Domain changes", &domain_changed);
+    print_section("Version changes", &version_changed);
+}
+
+fn print_section(title: &str, lines: &Vec<String>) {
+    if lines.len() == 0 {
+        return;
+    }
+
+    println!("\n{}:", title);
+    for line in lines {
+        println!("  {}", line);
+    }
+}
+
+/// Compares two values ignoring the case, since Windows names are case
+/// insensitive.
+fn changed(old: &Option<String>, new: &Option<String>) -> bool {
+    match (old, new) {
+        (Some(old), Some(new)) => !old.eq_ignore_ascii_case(new),
+        (None, None) => false,
+        _ => true,
+    }
+}
+
+fn domain_to_string(host: &ScanHost) -> String {
+    match (&host.nb_domain, &host.dns_domain) {
+        (Some(nb), Some(dns)) => format!("{} ({})", nb, dns),
+        (Some(nb), None) => nb.clone(),
+        (None, Some(dns)) => dns.clone(),
+        (None, None) => "-".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::challenge::{Challenge, ChallengeTarget, Host, Protocol};
+    use crate::printer::db::save_db;
+    use ntlm::ChallengeMsg;
+
+    fn temp_path(name: &str) -> String {
+        let path = std::env::temp_dir().join(format!(
+            "ntlm-info-{}-{}",
+            std::process::id(),
+            name
+        ));
+        return path.to_str().unwrap().to_string();
+    }
+
+    #[test]
+    fn load_json_by_port_and_protocol() {
+        let path = temp_path("diff.json");
+        std::fs::write(
+            &path,
+            r#"[
+                {"target": "10.0.0.1", "ip": "10.0.0.1", "port": 445,
+                 "protocol": "smb", "nb_computer": "SRV01"},
+                {"target": "http://10.0.0.1/", "ip": "10.0.0.1", "port": 80,
+                 "protocol": "http", "nb_computer": "SRV01"},
+                {"target": "10.0.0.1", "ip": "10.0.0.1", "port": 445,
+                 "protocol": "smb", "nb_computer": "SRV02"},
+                {"target": "::1", "ip": "::1", "port": 445, "protocol": "smb"},
+                {"target": "10.0.0.2", "error": "Timeout"}
+            ]"#,
+        )
+        .unwrap();
+        let scan = load_json(&path);
+        std::fs::remove_file(&path).unwrap();
+        let scan = scan.unwrap();
+
+        let keys: Vec<&str> = scan.keys().map(|k| k.as_str()).collect();
+        assert_eq!(
+            keys,
+            vec!["10.0.0.1:445/smb", "10.0.0.1:80/http", "[::1]:445/smb"]
+        );
+        assert_eq!(
+            scan["10.0.0.1:445/smb"].nb_computer.as_deref(),
+            Some("SRV01")
+        );
+    }
+
+    #[test]
+    fn load_db_empty_and_unknown_scans() {
+        let path = temp_path("diff.db");
+        let host = Host::new("10.0.0.1".parse().unwrap(), vec![]);
+        let mut ch = Challenge::new(
+            ChallengeTarget::Host(host),
+            ChallengeMsg::default(),
+        );
+        ch.protocol = Some(Protocol::Smb);
+
+        // the second scan has no results
+        let now = ntlm::time::now();
+        save_db(&path, &vec![ch], &now).unwrap();
+        save_db(&path, &vec![], &now).unwrap();
+
+        let found = load_db_scan(&path, 1).map(|scan| scan.len());
+        let empty = load_db_scan(&path, 2).map(|scan| scan.len());
+        let unknown = load_db_scan(&path, 3).map(|scan| scan.len());
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(found, Ok(1));
+        assert_eq!(empty, Ok(0));
+        assert!(unknown.is_err());
+    }
+}
@@ -0,0 +1,402 @@
+use crate::challenge::{Challenge, ChallengeTarget};
+use crate::dns::DnsResolver;
+use crate::range::Scope;
+use log::{info, warn};
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+use url::Url;
+
+/// SRV records of the services of the domain controllers, queried in the
+/// DNS domain of the challenges.
+const DOMAIN_SRV: &'static [&'static str] = &[
+    "_ldap._tcp.dc._msdcs",
+    "_ldap._tcp.pdc._msdcs",
+    "_kerberos._tcp.dc._msdcs",
+    "_kerberos._tcp",
+    "_kpasswd._tcp",
+    "_ldap._tcp",
+];
+
+/// SRV records of the global catalogs, queried in the forest (DNS tree) of
+/// the challenges.
+const FOREST_SRV: &'static [&'static str] =
+    &["_gc._tcp", "_ldap._tcp.gc._msdcs"];
+
+/// Host discovered from a challenge, with the protocol to scan it.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Discovered {
+    /// Name to scan with SMB.
+    Host(String),
+
+    /// URL to request with HTTP, for the names found in HTTP responses.
+    Url(String),
+}
+
+impl Discovered {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Discovered::Host(host) => host,
+            Discovered::Url(url) => url,
+        }
+    }
+}
+
+/// Discovers new hosts from the challenges, by querying the SRV records of
+/// the domain controllers and global catalogs of their domains and, in
+/// recursive mode, by following the names of the hosts. Each domain is only
+/// queried once and each host is only returned once, so it can be shared by
+/// all the workers.
+#[derive(Clone)]
+pub struct Discovery {
+    resolver: DnsResolver,
+    srv: bool,
+
+    /// Scope of the names to follow, if recursive.
+    follow: Option<Arc<Scope>>,
+
+    domains: Arc<Mutex<HashSet<String>>>,
+
+    /// Names and URLs already discovered or scanned.
+    hosts: Arc<Mutex<HashSet<String>>>,
+}
+
+impl Discovery {
+    pub fn new(
+        resolver: DnsResolver,
+        srv: bool,
+        follow: Option<Arc<Scope>>,
+    ) -> Self {
+        return Self {
+            resolver,
+            srv,
+            follow,
+            domains: Arc::new(Mutex::new(HashSet::new())),
+            hosts: Arc::new(Mutex::new(HashSet::new())),
+        };
+    }
+
+    /// Registers a host that is already a target, so it is not returned
+    /// when it is discovered.
+    pub fn add_target(&self, host: &str) {
+        self.new_host(&host.trim_end_matches('.').to_lowercase());
+    }
+
+    /// Registers an URL that is already a target, so it is not returned
+    /// when one of its names is found in a response.
+    pub fn add_url(&self, url: &str) {
+        self.new_host(url);
+    }
+
+    /// Returns the hosts discovered from the challenge that weren't
+    /// discovered or scanned before.
+    pub fn discover(&self, ch: &Challenge) -> Vec<Discovered> {
+        let mut discovered = Vec::new();
+        if self.srv {
+            discovered.extend(self.discover_srv(ch));
+        }
+        if let Some(scope) = &self.follow {
+            discovered.extend(self.follow_names(ch, scope));
+        }
+        return discovered;
+    }
+
+    fn discover_srv(&self, ch: &Challenge) -> Vec<Discovered> {
+        let mut queries = Vec::new();
+        if let Some(domain) = ch.challenge.dns_domain_name() {
+            queries.push((domain.to_lowercase(), DOMAIN_SRV));
+        }
+        if let Some(forest) = ch.challenge.dns_tree_name() {
+            queries.push((forest.to_lowercase(), FOREST_SRV));
+        }
+
+        let mut discovered = Vec::new();
+        for (domain, services) in queries {
+            if domain.len() == 0 || !self.new_domain(&domain, services) {
+                continue;
+            }
+
+            for service in services {
+                let name = format!("{}.{}", service, domain);
+                let hosts = match self.resolver.resolve_srv(&name) {
+                    Ok(hosts) => hosts,
+                    Err(err) => {
+                        warn!("{}", err);
+                        continue;
+                    }
+                };
+
+                for host in hosts {
+                    if self.new_host(&host) {
+                        info!("Discovered {} in {}", host, name);
+                        discovered.push(Discovered::Host(host));
+                    }
+                }
+            }
+        }
+
+        return discovered;
+    }
+
+    /// Returns the DNS computer name of the challenge, to scan with SMB, and
+    /// the names found while retrieving it (redirects and certificates) that
+    /// are in scope. These are requested with the URL of the challenge.
+    fn follow_names(&self, ch: &Challenge, scope: &Scope) -> Vec<Discovered> {
+        let url = match &ch.target {
+            ChallengeTarget::Url(url) => Url::parse(url).ok(),
+            _ => None,
+        };
+
+        let mut names: Vec<(&String, Option<&Url>)> = ch
+            .seen_names
+            .iter()
+            .map(|name| (name, url.as_ref()))
+            .collect();
+        if let Some(name) = ch.challenge.dns_computer_name() {
+            names.push((name, None));
+        }
+
+        let mut discovered = Vec::new();
+        for (name, url) in names {
+            let name = name.trim_end_matches('.').to_lowercase();
+            // wildcards of certificates can't be scanned
+            if name.len() == 0 || name.contains('*') {
+                continue;
+            }
+
+            let host = match url {
+                Some(url) => match with_host(url, &name) {
+                    Some(url) => Discovered::Url(url),
+                    None => continue,
+                },
+                None => Discovered::Host(name.clone()),
+            };
+            if self.is_known(host.as_str()) {
+                continue;
+            }
+
+            if !self.in_scope(&name, scope) {
+                info!("Skipping {} found in {}, out of scope", name, ch.target);
+                continue;
+            }
+
+            if self.new_host(host.as_str()) {
+                info!("Discovered {} in {}", host.as_str(), ch.target);
+                discovered.push(host);
+            }
+        }
+
+        return discovered;
+    }
+
+    /// Checks if the name matches a domain suffix of the scope or resolves
+    /// to an IP of its ranges.
+    fn in_scope(&self, name: &str, scope: &Scope) -> bool {
+        if scope.contains_name(name) {
+            return true;
+        }
+
+        if !scope.has_ranges() {
+            return false;
+        }
+
+        match self.resolver.resolve_hostname_all(name) {
+            Ok(ips) => ips.iter().any(|ip| scope.contains_ip(ip)),
+            Err(err) => {
+                warn!("{}", err);
+                false
+            }
+        }
+    }
+
+    /// Registers the domain to be queried for the given services. Returns
+    /// false if it was already queried.
+    fn new_domain(&self, domain: &str, services: &[&str]) -> bool {
+        // the same name could be domain and forest, with different services
+        let key = format!("{}/{}", services[0], domain);
+        return self
+            .domains
+            .lock()
+            .expect("Error locking discovered domains")
+            .insert(key);
+    }
+
+    fn is_known(&self, host: &str) -> bool {
+        return self
+            .hosts
+            .lock()
+            .expect("Error locking discovered hosts")
+            .contains(host);
+    }
+
+    fn new_host(&self, host: &str) -> bool {
+        return self
+            .hosts
+            .lock()
+            .expect("Error locking discovered hosts")
+            .insert(host.to_string());
+    }
+}
+
+/// Returns the URL with its host replaced by the name.
+fn with_host(url: &Url, name: &str) -> Option<String> {
+    let mut url = url.clone();
+    url.set_host(Some(name)).ok()?;
+    return Some(url.to_string());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ntlm::{AvPair, ChallengeMsg};
+    use std::net::UdpSocket;
+    use std::str::FromStr;
+    use std::thread;
+    use trust_dns_resolver::proto::op::{Message, MessageType, ResponseCode};
+    use trust_dns_resolver::proto::rr::rdata::SRV;
+    use trust_dns_resolver::proto::rr::{Name, RData, Record};
+
+    /// Starts a DNS server that answers the SRV records, given as (name,
+    /// target), and records the names queried. Returns its address.
+    fn srv_server(
+        records: &[(&str, &str)],
+        queried: Arc<Mutex<Vec<String>>>,
+    ) -> String {
+        let records: Vec<(String, String)> = records
+            .iter()
+            .map(|(name, target)| (format!("{}.", name), target.to_string()))
+            .collect();
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = socket.local_addr().unwrap().to_string();
+
+        thread::spawn(move || loop {
+            let mut request = [0; 512];
+            let (len, src) = socket.recv_from(&mut request).unwrap();
+            let query = Message::from_vec(&request[..len]).unwrap();
+
+            let mut response = Message::new();
+            response
+                .set_id(query.id())
+                .set_message_type(MessageType::Response)
+                .set_recursion_desired(true)
+                .set_recursion_available(true)
+                .add_queries(query.queries().to_vec());
+            for q in query.queries() {
+                let name = q.name().to_utf8();
+                queried.lock().unwrap().push(name.clone());
+                for (_, target) in records.iter().filter(|(n, _)| *n == name) {
+                    let target = Name::from_str(target).unwrap();
+                    let srv = SRV::new(0, 100, 389, target);
+                    response.add_answer(Record::from_rdata(
+                        q.name().clone(),
+                        0,
+                        RData::SRV(srv),
+                    ));
+                }
+            }
+            if response.answers().len() == 0 {
+                response.set_response_code(ResponseCode::NXDomain);
+            }
+
+            socket.send_to(&response.to_vec().unwrap(), src).unwrap();
+        });
+
+        return addr;
+    }
+
+    fn domain_challenge(domain: &str, forest: &str) -> Challenge {
+        let mut msg = ChallengeMsg::default();
+        msg.target_info = vec![
+            AvPair::DnsDomainName(domain.to_string()),
+            AvPair::DnsTreeName(forest.to_string()),
+            AvPair::EOL,
+        ]
+        .into();
+        return Challenge::new("http://ws01/".into(), msg);
+    }
+
+    #[test]
+    fn discover_srv_once_per_domain_and_forest() {
+        let queried = Arc::new(Mutex::new(Vec::new()));
+        let server = srv_server(
+            &[
+                ("_ldap._tcp.dc._msdcs.contoso.local", "dc01.contoso.local."),
+                ("_ldap._tcp.dc._msdcs.contoso.local", "DC02.contoso.local."),
+                ("_kerberos._tcp.contoso.local", "dc01.contoso.local."),
+                // the service is not available
+                ("_gc._tcp.contoso.local", "."),
+                ("_ldap._tcp.gc._msdcs.contoso.local", "gc01.contoso.local."),
+                ("_ldap._tcp.sub.contoso.local", "dc03.sub.contoso.local."),
+            ],
+            queried.clone(),
+        );
+        let resolver = DnsResolver::new(&[server], false, &[]).unwrap();
+        let discovery = Discovery::new(resolver, true, None);
+
+        let hosts = |domain: &str, forest: &str| {
+            return discovery.discover(&domain_challenge(domain, forest));
+        };
+        assert_eq!(
+            hosts("CONTOSO.local", "contoso.local"),
+            vec![
+                Discovered::Host("dc01.contoso.local".to_string()),
+                Discovered::Host("dc02.contoso.local".to_string()),
+                Discovered::Host("gc01.contoso.local".to_string()),
+            ]
+        );
+        assert_eq!(
+            hosts("sub.contoso.local", "CONTOSO.LOCAL"),
+            vec![Discovered::Host("dc03.sub.contoso.local".to_string())]
+        );
+        assert_eq!(hosts("contoso.local", "contoso.local").len(), 0);
+
+        // the forest is only queried once, and the domains once each
+        let queries = [
+            (DOMAIN_SRV, "contoso.local"),
+            (FOREST_SRV, "contoso.local"),
+            (DOMAIN_SRV, "sub.contoso.local"),
+        ];
+        let mut expected: Vec<String> = queries
+            .iter()
+            .flat_map(|(services, domain)| {
+                services.iter().map(move |s| format!("{}.{}.", s, domain))
+            })
+            .collect();
+        let mut queried = queried.lock().unwrap().clone();
+        queried.sort();
+        expected.sort();
+        assert_eq!(queried, expected);
+    }
+
+    #[test]
+    fn follow_http_names_with_the_url() {
+        let scope = Scope::new(&["contoso.local".to_string()]);
+        let discovery = Discovery::new(
+            DnsResolver::new(&[], false, &[]).unwrap(),
+            false,
+            Some(Arc::new(scope)),
+        );
+        let url = "https://mail.contoso.local/owa/";
+        discovery.add_url(url);
+
+        let mut msg = ChallengeMsg::default();
+        msg.target_info =
+            vec![AvPair::DnsComputerName("EX01.contoso.local".to_string())]
+                .into();
+        let mut ch = Challenge::new(url.into(), msg);
+        ch.seen_names = vec![
+            "mail.contoso.local".to_string(),
+            "OWA.contoso.local".to_string(),
+            "*.contoso.local".to_string(),
+            "www.example.com".to_string(),
+        ];
+
+        assert_eq!(
+            discovery.discover(&ch),
+            vec![
+                Discovered::Url("https://owa.contoso.local/owa/".to_string()),
+                Discovered::Host("ex01.contoso.local".to_string()),
+            ]
+        );
+        assert_eq!(discovery.discover(&ch).len(), 0);
+    }
+}
@@ -1,18 +1,366 @@
-use std::net::IpAddr;
-use trust_dns_resolver::Resolver;
+use crate::error::Error;
+use hyper::client::connect::dns::Name as HostName;
+use reqwest::dns::{Addrs, Resolve, Resolving};
+use std::fmt;
+use std::future::Future;
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
+use std::sync::Arc;
+use tokio::runtime::Runtime;
+use trust_dns_resolver::config::{
+    NameServerConfig, Protocol, ResolverConfig, ResolverOpts,
+};
+use trust_dns_resolver::error::{ResolveError, ResolveErrorKind};
+use trust_dns_resolver::lookup_ip::LookupIp;
+use trust_dns_resolver::system_conf::read_system_conf;
+use trust_dns_resolver::Name;
+use trust_dns_resolver::TokioAsyncResolver;
 
-pub fn resolve_hostname(hostname: &str) -> Result<IpAddr, String> {
-    let resolver = Resolver::from_system_conf()
-        .map_err(|err| format!("Error resolving '{}' : '{}'", hostname, err))?;
+/// Port used for DNS servers given without port.
+const DNS_PORT: u16 = 53;
 
-    let ips = resolver
-        .lookup_ip(hostname)
-        .map_err(|err| format!("Error resolving '{}' : '{}'", hostname, err))?;
+/// Number of hostnames resolved at the same time.
+const CONCURRENT_LOOKUPS: usize = 256;
 
-    let ip = ips
-        .iter()
-        .next()
-        .ok_or(format!("Error resolving '{}': No entries found", hostname))?;
+/// Number of lookups kept in the resolver cache.
+const CACHE_SIZE: usize = 4096;
 
-    return Ok(ip);
+/// Resolver shared by all the workers. Lookups are cached, so targets of
+/// the same hostname are only resolved once.
+#[derive(Clone)]
+pub struct DnsResolver {
+    runtime: Arc<Runtime>,
+    resolver: TokioAsyncResolver,
+}
+
+impl DnsResolver {
+    /// Creates a resolver that uses the given servers (`ip`, `ip:port` or
+    /// `[ipv6]:port`) or, if none, the system configuration. Search domains
+    /// are appended to the ones of the system configuration.
+    pub fn new(
+        servers: &[String],
+        tcp: bool,
+        search: &[String],
+    ) -> Result<Self, String> {
+        let (config, mut opts) = if servers.len() == 0 {
+            read_system_conf().map_err(|err| {
+                format!("Error reading system DNS configuration: {}", err)
+            })?
+        } else {
+            (ResolverConfig::new(), ResolverOpts::default())
+        };
+        opts.cache_size = CACHE_SIZE;
+
+        let mut search_domains = config.search().to_vec();
+        for domain in search {
+            let domain = Name::from_str(domain).map_err(|err| {
+                format!("Invalid search domain '{}': {}", domain, err)
+            })?;
+            search_domains.push(domain);
+        }
+
+        let mut name_servers = config.name_servers().to_vec();
+        if servers.len() != 0 {
+            let protocol = if tcp { Protocol::Tcp } else { Protocol::Udp };
+            name_servers = servers
+                .iter()
+                .map(|s| parse_name_server(s, protocol))
+                .collect::<Result<_, String>>()?;
+        } else if tcp {
+            for ns in name_servers.iter_mut() {
+                ns.protocol = Protocol::Tcp;
+            }
+        }
+
+        let config = ResolverConfig::from_parts(
+            config.domain().cloned(),
+            search_domains,
+            name_servers,
+        );
+
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(2)
+            .enable_all()
+            .build()
+            .map_err(|err| format!("Error creating DNS runtime: {}", err))?;
+
+        let resolver = TokioAsyncResolver::tokio(config, opts)
+            .map_err(|err| format!("Error creating DNS resolver: {}", err))?;
+
+        return Ok(Self {
+            runtime: Arc::new(runtime),
+            resolver,
+        });
+    }
+
+    /// Runs a future in the runtime of the resolver, which is required by
+    /// the HTTP requests that resolve their hosts with it.
+    pub fn block_on<F: Future>(&self, future: F) -> F::Output {
+        return self.runtime.block_on(future);
+    }
+
+    /// Returns all the IPs (A and AAAA records) of the hostname.
+    pub fn resolve_hostname_all(
+        &self,
+        hostname: &str,
+    ) -> Result<Vec<IpAddr>, Error> {
+        let lookup = self.runtime.block_on(self.resolver.lookup_ip(hostname));
+        return lookup_ips(hostname, lookup);
+    }
+
+    /// Resolves several hostnames at the same time, returning the IPs of
+    /// each one in the same order.
+    pub fn resolve_hostnames(
+        &self,
+        hostnames: &[String],
+    ) -> Vec<Result<Vec<IpAddr>, Error>> {
+        let mut results = Vec::with_capacity(hostnames.len());
+        for chunk in hostnames.chunks(CONCURRENT_LOOKUPS) {
+            let lookups: Vec<_> = chunk
+                .iter()
+                .map(|hostname| {
+                    let resolver = self.resolver.clone();
+                    let hostname = hostname.clone();
+                    self.runtime.spawn(async move {
+                        resolver.lookup_ip(hostname.as_str()).await
+                    })
+                })
+                .collect();
+
+            for (hostname, lookup) in chunk.iter().zip(lookups) {
+                let result = match self.runtime.block_on(lookup) {
+                    Ok(lookup) => lookup_ips(hostname, lookup),
+                    Err(err) => Err(Error::Dns(format!(
+                        "Error resolving '{}' : '{}'",
+                        hostname, err
+                    ))),
+                };
+                results.push(result);
+            }
+        }
+
+        return results;
+    }
+
+    /// Returns the hosts of the SRV records of the name.
+    pub fn resolve_srv(&self, name: &str) -> Result<Vec<String>, Error> {
+        let fqdn = format!("{}.", name.trim_end_matches('.'));
+        let hosts: Vec<String> = self
+            .runtime
+            .block_on(self.resolver.srv_lookup(fqdn.as_str()))
+            .map(|srvs| {
+                srvs.iter()
+                    .map(|srv| normalize_name(&srv.target().to_utf8()))
+                    .collect()
+            })
+            .or_else(empty_if_not_found)
+            .map_err(|err| {
+                Error::Dns(format!(
+                    "Error resolving SRV '{}' : '{}'",
+                    name, err
+                ))
+            })?;
+
+        // a target of "." means that the service is not available
+        return Ok(hosts.into_iter().filter(|h| h.len() != 0).collect());
+    }
+
+    /// Compares the name of the host reported in the NTLM challenge with
+    /// the PTR records of its IP and the A/AAAA records of the name.
+    pub fn check(&self, ip: IpAddr, name: &str) -> Result<DnsCheck, Error> {
+        let dns_err = |err: ResolveError| {
+            Error::Dns(format!("Error checking DNS of '{}' : '{}'", name, err))
+        };
+
+        let ptr = self
+            .runtime
+            .block_on(self.resolver.reverse_lookup(ip))
+            .map(|names| {
+                names.iter().map(|n| normalize_name(&n.to_utf8())).collect()
+            })
+            .or_else(empty_if_not_found)
+            .map_err(dns_err)?;
+
+        // trailing dot to avoid appending the search domains
+        let fqdn = format!("{}.", name.trim_end_matches('.'));
+        let mut forward: Vec<IpAddr> = self
+            .runtime
+            .block_on(self.resolver.ipv4_lookup(fqdn.as_str()))
+            .map(|ips| ips.iter().map(|ip| IpAddr::V4(*ip)).collect())
+            .or_else(empty_if_not_found)
+            .map_err(dns_err)?;
+        let ipv6: Vec<IpAddr> = self
+            .runtime
+            .block_on(self.resolver.ipv6_lookup(fqdn.as_str()))
+            .map(|ips| ips.iter().map(|ip| IpAddr::V6(*ip)).collect())
+            .or_else(empty_if_not_found)
+            .map_err(dns_err)?;
+        forward.extend(ipv6);
+
+        let status = status(&ip, name, &ptr, &forward);
+        return Ok(DnsCheck {
+            ptr,
+            forward,
+            status,
+        });
+    }
+}
+
+/// Resolves the hosts of the HTTP requests, including the ones of the
+/// redirects, with the same servers and search domains than the rest of
+/// targets.
+impl Resolve for DnsResolver {
+    fn resolve(&self, name: HostName) -> Resolving {
+        let resolver = self.resolver.clone();
+        return Box::pin(async move {
+            let lookup = resolver.lookup_ip(name.as_str()).await?;
+            let addrs: Addrs =
+                Box::new(lookup.into_iter().map(|ip| SocketAddr::new(ip, 0)));
+            return Ok(addrs);
+        });
+    }
+}
+
+/// Result of comparing the DNS records of a host with the name reported
+/// in its NTLM challenge.
+pub struct DnsCheck {
+    /// Names of the PTR records of the IP.
+    pub ptr: Vec<String>,
+
+    /// IPs of the A/AAAA records of the NTLM name.
+    pub forward: Vec<IpAddr>,
+
+    pub status: DnsStatus,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DnsStatus {
+    /// The name resolves to the IP and the PTR points to the name.
+    Match,
+
+    /// The name resolves to the IP, but the PTR points to other name.
+    StalePtr,
+
+    /// There is no A/AAAA record of the name.
+    MissingForward,
+
+    /// The name resolves to the IP, but there is no PTR of the IP.
+    MissingPtr,
+
+    /// The name resolves to other IPs, which happens with NAT, load
+    /// balancers or relays.
+    Elsewhere,
+}
+
+impl fmt::Display for DnsStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Match => write!(f, "match"),
+            Self::StalePtr => write!(f, "stale-ptr"),
+            Self::MissingForward => write!(f, "missing-forward"),
+            Self::MissingPtr => write!(f, "missing-ptr"),
+            Self::Elsewhere => write!(f, "elsewhere"),
+        }
+    }
+}
+
+/// Classifies the result of comparing the PTR records of the IP and the
+/// A/AAAA records of the name reported by the host.
+fn status(
+    ip: &IpAddr,
+    name: &str,
+    ptr: &[String],
+    forward: &[IpAddr],
+) -> DnsStatus {
+    if forward.len() == 0 {
+        return DnsStatus::MissingForward;
+    }
+    if !forward.contains(ip) {
+        return DnsStatus::Elsewhere;
+    }
+    if ptr.len() == 0 {
+        return DnsStatus::MissingPtr;
+    }
+    if !ptr.contains(&normalize_name(name)) {
+        return DnsStatus::StalePtr;
+    }
+    return DnsStatus::Match;
+}
+
+fn lookup_ips(
+    hostname: &str,
+    lookup: Result<LookupIp, ResolveError>,
+) -> Result<Vec<IpAddr>, Error> {
+    let ips = lookup.map_err(|err| {
+        Error::Dns(format!("Error resolving '{}' : '{}'", hostname, err))
+    })?;
+
+    let ips: Vec<IpAddr> = ips.iter().collect();
+    if ips.len() == 0 {
+        return Err(Error::Dns(format!(
+            "Error resolving '{}': No entries found",
+            hostname
+        )));
+    }
+
+    return Ok(ips);
+}
+
+/// Lowercase name without the trailing dot.
+fn normalize_name(name: &str) -> String {
+    return name.trim_end_matches('.').to_lowercase();
+}
+
+fn empty_if_not_found<T>(err: ResolveError) -> Result<Vec<T>, ResolveError> {
+    match err.kind() {
+        ResolveErrorKind::NoRecordsFound { .. } => Ok(Vec::new()),
+        _ => Err(err),
+    }
+}
+
+fn parse_name_server(
+    server: &str,
+    protocol: Protocol,
+) -> Result<NameServerConfig, String> {
+    let socket_addr = match server.parse::<SocketAddr>() {
+        Ok(addr) => addr,
+        Err(_) => match server.parse::<IpAddr>() {
+            Ok(ip) => SocketAddr::new(ip, DNS_PORT),
+            Err(_) => {
+                return Err(format!("Invalid DNS server '{}'", server));
+            }
+        },
+    };
+
+    return Ok(NameServerConfig {
+        socket_addr,
+        protocol,
+        tls_dns_name: None,
+        trust_nx_responses: true,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classify_dns_records() {
+        let ip: IpAddr = "10.0.0.1".parse().unwrap();
+        let other: IpAddr = "10.0.0.2".parse().unwrap();
+        let name = "WS01.contoso.local.";
+        let ptr = vec!["ws01.contoso.local".to_string()];
+        let stale = vec!["old.contoso.local".to_string()];
+
+        let check = |ptr: &[String], forward: &[IpAddr]| {
+            return status(&ip, name, ptr, forward);
+        };
+        assert_eq!(check(&ptr, &[other, ip]), DnsStatus::Match);
+        assert_eq!(check(&stale, &[ip]), DnsStatus::StalePtr);
+        assert_eq!(check(&[], &[ip]), DnsStatus::MissingPtr);
+        assert_eq!(check(&ptr, &[]), DnsStatus::MissingForward);
+        assert_eq!(check(&[], &[]), DnsStatus::MissingForward);
+        assert_eq!(check(&ptr, &[other]), DnsStatus::Elsewhere);
+        assert_eq!(DnsStatus::MissingPtr.to_string(), "missing-ptr");
+    }
 }
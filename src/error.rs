@@ -0,0 +1,78 @@
+use std::fmt;
+use std::io;
+
+/// Error produced while retrieving the NTLM challenge from a target. It is
+/// classified by its cause in order to know which targets could be
+/// retried or scanned in a different way.
+#[derive(Debug)]
+pub enum Error {
+    /// The target hostname couldn't be resolved.
+    Dns(String),
+
+    /// The target refused the connection, usually the port is closed.
+    ConnectionRefused(String),
+
+    /// Any other connection error, like an unreachable host or a reset.
+    Connection(String),
+
+    /// The target didn't respond in time.
+    Timeout(String),
+
+    /// The target responded something unexpected.
+    Protocol(String),
+
+    /// The target doesn't offer NTLM authentication.
+    NoNtlm(String),
+
+    /// The NTLM challenge sent by the target couldn't be decoded.
+    Parse(String),
+
+    /// The target redirected to a host that is excluded or out of scope.
+    Excluded(String),
+}
+
+impl Error {
+    /// Classifies an IO error produced in the communication with a target.
+    pub fn from_io(err: &io::Error, msg: String) -> Self {
+        match err.kind() {
+            io::ErrorKind::ConnectionRefused => Self::ConnectionRefused(msg),
+            io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => {
+                Self::Timeout(msg)
+            }
+            _ => Self::Connection(msg),
+        }
+    }
+
+    /// Returns the name of the error category.
+    pub fn category(&self) -> &'static str {
+        match self {
+            Self::Dns(_) => "dns",
+            Self::ConnectionRefused(_) => "connection_refused",
+            Self::Connection(_) => "connection",
+            Self::Timeout(_) => "timeout",
+            Self::Protocol(_) => "protocol",
+            Self::NoNtlm(_) => "no_ntlm",
+            Self::Parse(_) => "parse",
+            Self::Excluded(_) => "excluded",
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        match self {
+            Self::Dns(msg)
+            | Self::ConnectionRefused(msg)
+            | Self::Connection(msg)
+            | Self::Timeout(msg)
+            | Self::Protocol(msg)
+            | Self::NoNtlm(msg)
+            | Self::Parse(msg)
+            | Self::Excluded(msg) => msg,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message())
+    }
+}
@@ -1,22 +1,108 @@
+mod tls;
+
 use crate::auth;
 use crate::challenge::Challenge;
+use crate::challenge::Protocol;
+use crate::dns::DnsResolver;
+use crate::error::Error;
+use crate::range::{Exclusions, Scope};
+use crate::readin::ScanHost;
+use chrono::{DateTime, Utc};
+use hyper::client::connect::dns::Name as HostName;
+use log::warn;
+use ntlm::time::NtlmTime;
 use ntlm::ChallengeMsg;
-use reqwest::blocking::Client;
-use reqwest::blocking::Response;
+use reqwest::dns::{Addrs, Resolve, Resolving};
+use reqwest::redirect::Policy;
+use reqwest::tls::TlsInfo;
+use reqwest::{Client, Response};
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
+use trust_dns_resolver::error::ResolveError;
+use url::{Host, Url};
 
 const AUTH_HEADER: &'static str = "www-authenticate";
+const DATE_HEADER: &'static str = "date";
+
+/// Maximum number of redirects to follow, the same of the reqwest default
+/// policy.
+const MAX_REDIRECTS: usize = 10;
+
+/// Ports usually used by web servers, to identify them in the results of
+/// port scanners without service detection.
+const HTTP_PORTS: &'static [u16] = &[80, 8000, 8008, 8080, 8888];
+const HTTPS_PORTS: &'static [u16] = &[443, 4443, 8443];
 
-#[derive(Clone, Debug, Copy)]
+/// WinRM ports, that offer NTLM authentication in the /wsman endpoint.
+const WINRM_HTTP_PORT: u16 = 5985;
+const WINRM_HTTPS_PORT: u16 = 5986;
+
+#[derive(Clone)]
 pub struct HttpOptions {
     pub timeout: Duration,
+
+    /// Retrieve the names of the TLS certificate of HTTPS servers.
+    pub tls_names: bool,
+
+    /// Request every IP of the hostnames of the URLs, instead of only the
+    /// first one.
+    pub all_ips: bool,
+
+    /// Resolver of the hosts of the URLs and their redirects.
+    pub resolver: DnsResolver,
+
+    /// Hosts that must not be requested, also in the redirects.
+    pub exclusions: Arc<Exclusions>,
+
+    /// Scope of the redirects, in recursive mode.
+    pub scope: Option<Arc<Scope>>,
 }
 
+/// Retrieves the challenge of the URL, connecting to the given IPs of its
+/// host, which was resolved before to remove the excluded IPs.
 pub fn challenge_http(
     url: &str,
-    options: HttpOptions,
-) -> Result<Challenge, String> {
-    let client = Client::new();
+    options: &HttpOptions,
+    ips: &[IpAddr],
+) -> Result<Challenge, Error> {
+    // the requests must run in the runtime of the resolver
+    return options
+        .resolver
+        .block_on(request_challenge(url, options, ips));
+}
+
+async fn request_challenge(
+    url: &str,
+    options: &HttpOptions,
+    ips: &[IpAddr],
+) -> Result<Challenge, Error> {
+    let parsed = Url::parse(url)
+        .map_err(|e| Error::Parse(format!("Invalid URL {}: {}", url, e)))?;
+    let guard = RedirectGuard {
+        resolver: options.resolver.clone(),
+        exclusions: options.exclusions.clone(),
+        scope: options.scope.clone(),
+        target: parsed.host_str().unwrap_or("").to_string(),
+    };
+
+    // hosts of the redirects, which could be other servers
+    let redirects = Arc::new(Mutex::new(Vec::new()));
+    let mut builder = Client::builder()
+        .redirect(redirect_policy(redirects.clone(), guard.clone()))
+        .dns_resolver(Arc::new(guard))
+        .tls_info(options.tls_names);
+
+    if let (Some(host), Some(port)) =
+        (parsed.domain(), parsed.port_or_known_default())
+    {
+        let addrs: Vec<SocketAddr> =
+            ips.iter().map(|ip| SocketAddr::new(*ip, port)).collect();
+        builder = builder.resolve_to_addrs(host, &addrs);
+    }
+
+    let client = builder.build().map_err(|e| request_error(e, url))?;
     let neg_b64 = base64::encode(auth::new_ntlm_negotiate().build());
 
     let resp = client
@@ -24,42 +110,340 @@ pub fn challenge_http(
         .timeout(options.timeout)
         .header("Authorization", format!("NTLM {}", neg_b64))
         .send()
-        .map_err(|e| format!("Error requesting {}: {}", url, e))?;
+        .await
+        .map_err(|e| request_error(e, url))?;
 
     let ntlm_challenge = extract_challenge(&resp)?;
 
-    return Ok(Challenge::new(url.into(), ntlm_challenge));
+    let mut challenge = Challenge::new(url.into(), ntlm_challenge);
+    challenge.address = resp.remote_addr();
+    challenge.protocol = Some(Protocol::Http);
+    challenge.http_date = extract_date(&resp);
+    challenge.seen_names = redirects.lock().unwrap().clone();
+
+    // the certificate of the same connection that returned the challenge
+    let certificate = resp
+        .extensions()
+        .get::<TlsInfo>()
+        .and_then(|info| info.peer_certificate());
+    if let Some(der) = certificate {
+        match tls::certificate_names(der) {
+            Ok(names) => challenge.seen_names.extend(names),
+            Err(err) => warn!("{}: {}", url, err),
+        }
+    }
+
+    return Ok(challenge);
+}
+
+/// Returns the IPs of the host of the URL, resolving it if it is a name.
+pub fn url_ips(
+    url: &str,
+    resolver: &DnsResolver,
+) -> Result<Vec<IpAddr>, Error> {
+    let parsed = Url::parse(url)
+        .map_err(|e| Error::Parse(format!("Invalid URL {}: {}", url, e)))?;
+
+    match parsed.host() {
+        Some(Host::Ipv4(ip)) => Ok(vec![IpAddr::V4(ip)]),
+        Some(Host::Ipv6(ip)) => Ok(vec![IpAddr::V6(ip)]),
+        Some(Host::Domain(name)) => resolver.resolve_hostname_all(name),
+        None => Err(Error::Parse(format!("No host in URL {}", url))),
+    }
+}
+
+/// Follows the redirects as the default policy, saving their hosts, unless
+/// they go to a host that must not be requested.
+fn redirect_policy(
+    redirects: Arc<Mutex<Vec<String>>>,
+    guard: RedirectGuard,
+) -> Policy {
+    return Policy::custom(move |attempt| {
+        if attempt.previous().len() >= MAX_REDIRECTS {
+            return attempt.error("too many redirects");
+        }
+
+        if let Some(host) = attempt.url().host_str() {
+            let host = host.to_lowercase();
+            if let Err(err) = guard.check_host(&host) {
+                return attempt.error(err);
+            }
+            redirects.lock().unwrap().push(host);
+        }
+        return attempt.follow();
+    });
+}
+
+/// Checks the hosts of the redirects, so the excluded ones and, in
+/// recursive mode, the ones out of scope are not requested. Names are
+/// checked by their IPs when they are resolved. The host of the URL is a
+/// target, so it is not checked against the scope.
+#[derive(Clone)]
+struct RedirectGuard {
+    resolver: DnsResolver,
+    exclusions: Arc<Exclusions>,
+    scope: Option<Arc<Scope>>,
+    target: String,
+}
+
+impl RedirectGuard {
+    fn check_host(&self, host: &str) -> Result<(), Forbidden> {
+        if self.exclusions.contains_host(host) {
+            return Err(Forbidden(format!(
+                "Redirect to excluded host {}",
+                host
+            )));
+        }
+
+        if host == self.target {
+            return Ok(());
+        }
+
+        let ip = host
+            .trim_matches(|c| c == '[' || c == ']')
+            .parse::<IpAddr>();
+        match (&self.scope, ip) {
+            (Some(scope), Ok(ip)) if !scope.contains_ip(&ip) => {
+                Err(Forbidden(format!("Redirect to {}, out of scope", host)))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns the IPs of the name that can be requested.
+    fn filter_ips(&self, name: &str, ips: Vec<IpAddr>) -> Vec<IpAddr> {
+        let ips = ips
+            .into_iter()
+            .filter(|ip| !self.exclusions.contains_ip(ip))
+            .collect();
+        match &self.scope {
+            Some(scope) if name != self.target => scope.filter_ips(name, ips),
+            _ => ips,
+        }
+    }
+}
+
+impl Resolve for RedirectGuard {
+    fn resolve(&self, name: HostName) -> Resolving {
+        let guard = self.clone();
+        return Box::pin(async move {
+            let host = name.as_str().to_lowercase();
+            let addrs = guard.resolver.resolve(name).await?;
+            let ips = guard.filter_ips(&host, addrs.map(|a| a.ip()).collect());
+            if ips.len() == 0 {
+                let msg =
+                    format!("Redirect to {}, excluded or out of scope", host);
+                let err: Box<dyn std::error::Error + Send + Sync> =
+                    Box::new(Forbidden(msg));
+                return Err(err);
+            }
+
+            let addrs: Addrs =
+                Box::new(ips.into_iter().map(|ip| SocketAddr::new(ip, 0)));
+            return Ok(addrs);
+        });
+    }
+}
+
+/// Error of the requests to hosts that are excluded or out of scope.
+#[derive(Debug)]
+struct Forbidden(String);
+
+impl fmt::Display for Forbidden {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for Forbidden {}
+
+/// Selects the web servers of the hosts found by a port scanner, by the
+/// detected service or by the port.
+pub fn http_scan_targets(host: &ScanHost) -> Vec<String> {
+    let mut urls = Vec::new();
+    for port in host.ports.iter() {
+        let service = port.service.as_deref().unwrap_or("");
+        let (tls, path) = match port.port {
+            WINRM_HTTP_PORT => (false, "wsman"),
+            WINRM_HTTPS_PORT => (true, "wsman"),
+            p if HTTPS_PORTS.contains(&p) => (true, ""),
+            p if HTTP_PORTS.contains(&p) => (port.tls, ""),
+            _ if service.starts_with("http") => (port.tls, ""),
+            _ => continue,
+        };
+
+        let scheme = if tls || service == "https" {
+            "https"
+        } else {
+            "http"
+        };
+        urls.push(format!(
+            "{}://{}/{}",
+            scheme,
+            host.addr_port(port.port),
+            path
+        ));
+    }
+
+    return urls;
+}
+
+/// Classifies the error produced by an HTTP request.
+fn request_error(err: reqwest::Error, url: &str) -> Error {
+    let msg = format!("Error requesting {}: {}", url, err);
+
+    // the redirects to forbidden hosts fail in the policy or the resolver
+    let mut source = std::error::Error::source(&err);
+    while let Some(e) = source {
+        if e.downcast_ref::<Forbidden>().is_some() {
+            return Error::Excluded(msg);
+        }
+        source = e.source();
+    }
+
+    if err.is_timeout() {
+        return Error::Timeout(msg);
+    }
+
+    if !err.is_connect() {
+        return Error::Protocol(msg);
+    }
+
+    // the cause of the connection error is in the chain of sources
+    let mut source = std::error::Error::source(&err);
+    while let Some(e) = source {
+        if let Some(io_err) = e.downcast_ref::<std::io::Error>() {
+            return Error::from_io(io_err, msg);
+        }
+
+        // hosts are resolved with the DnsResolver
+        if e.downcast_ref::<ResolveError>().is_some() {
+            return Error::Dns(msg);
+        }
+        source = e.source();
+    }
+
+    return Error::Connection(msg);
 }
 
-fn extract_challenge(resp: &Response) -> Result<ChallengeMsg, String> {
-    let auth_header = resp.headers().get(AUTH_HEADER).ok_or(format!(
-        "No NTLM challenge in HTTP response (no {} header)",
-        AUTH_HEADER
-    ))?;
+/// Retrieves the server time from the Date header, if any.
+fn extract_date(resp: &Response) -> Option<NtlmTime> {
+    let date = resp.headers().get(DATE_HEADER)?.to_str().ok()?;
+    let date = DateTime::parse_from_rfc2822(date).ok()?;
+    return Some(date.with_timezone(&Utc));
+}
+
+fn extract_challenge(resp: &Response) -> Result<ChallengeMsg, Error> {
+    let auth_header =
+        resp.headers()
+            .get(AUTH_HEADER)
+            .ok_or(Error::NoNtlm(format!(
+                "No NTLM challenge in HTTP response (no {} header)",
+                AUTH_HEADER
+            )))?;
 
     let auth_header = auth_header
         .to_str()
-        .map_err(|_| format!("Error decoding NTLM challenge"))?;
+        .map_err(|_| Error::Parse(format!("Error decoding NTLM challenge")))?;
 
     if !auth_header.contains("NTLM") {
-        return Err(format!(
+        return Err(Error::NoNtlm(format!(
             "No NTLM challenge in HTTP response (Not supported)"
-        ));
+        )));
     }
 
     let parts: Vec<&str> = auth_header.split(" ").collect();
 
     if parts.len() < 2 {
-        return Err(format!("Error decoding NTLM challenge"));
+        return Err(Error::Parse(format!("Error decoding NTLM challenge")));
     }
 
     let challenge_b64 = parts[1];
 
     let challenge_raw = base64::decode(challenge_b64)
-        .map_err(|_| format!("Error decoding NTLM challenge"))?;
+        .map_err(|_| Error::Parse(format!("Error decoding NTLM challenge")))?;
 
     let challenge = ChallengeMsg::parse(&challenge_raw)
-        .map_err(|_| format!("Error decoding NTLM challenge"))?;
+        .map_err(|_| Error::Parse(format!("Error decoding NTLM challenge")))?;
 
     return Ok(challenge);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    #[test]
+    fn forbid_excluded_and_out_of_scope_redirects() {
+        let guard = |scope: Option<&str>| RedirectGuard {
+            resolver: DnsResolver::new(&[], false, &[]).unwrap(),
+            exclusions: Arc::new(
+                Exclusions::new(
+                    &vec!["10.0.0.1,db.contoso.local".to_string()],
+                    &vec![],
+                )
+                .unwrap(),
+            ),
+            scope: scope.map(|s| Arc::new(Scope::new(&[s.to_string()]))),
+            target: "192.168.0.1".to_string(),
+        };
+        let ips: Vec<IpAddr> =
+            vec!["10.0.0.1".parse().unwrap(), "10.0.0.2".parse().unwrap()];
+
+        let g = guard(None);
+        assert!(g.check_host("10.0.0.1").is_err());
+        assert!(g.check_host("db.contoso.local").is_err());
+        assert!(g.check_host("10.0.0.2").is_ok());
+        assert_eq!(g.filter_ips("web.contoso.local", ips.clone()), &ips[1..]);
+
+        // the host of the URL can be out of scope, and names are checked
+        // by their IPs
+        let g = guard(Some("10.0.0.0/8"));
+        assert!(g.check_host("192.168.0.1").is_ok());
+        assert!(g.check_host("192.168.0.2").is_err());
+        assert!(g.check_host("[::1]").is_err());
+        assert!(g.check_host("web.example.com").is_ok());
+        assert_eq!(g.filter_ips("web.example.com", ips.clone()), &ips[1..]);
+        let other = vec!["192.168.0.2".parse().unwrap()];
+        assert_eq!(g.filter_ips("web.example.com", other).len(), 0);
+    }
+
+    #[test]
+    fn stop_redirects_to_excluded_hosts() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = [0; 4096];
+            let _ = stream.read(&mut request);
+            let response = format!(
+                "HTTP/1.1 302 Found\r\nLocation: http://127.0.0.2:{}/\r\n\
+                 Content-Length: 0\r\n\r\n",
+                port
+            );
+            stream.write_all(response.as_bytes()).unwrap();
+        });
+
+        let options = HttpOptions {
+            timeout: Duration::from_secs(5),
+            tls_names: false,
+            all_ips: false,
+            resolver: DnsResolver::new(&[], false, &[]).unwrap(),
+            exclusions: Arc::new(
+                Exclusions::new(&vec!["127.0.0.2".to_string()], &vec![])
+                    .unwrap(),
+            ),
+            scope: None,
+        };
+        let url = format!("http://127.0.0.1:{}/", port);
+        let ip = "127.0.0.1".parse().unwrap();
+        match challenge_http(&url, &options, &[ip]) {
+            Err(Error::Excluded(_)) => {}
+            Err(err) => panic!("Unexpected error: {}", err),
+            Ok(_) => panic!("Redirect followed"),
+        }
+    }
+}
@@ -0,0 +1,189 @@
+/// Encoded OID of the subjectAltName extension (2.5.29.17).
+const SAN_OID: &'static [u8] = &[0x55, 0x1d, 0x11];
+
+const SEQUENCE_TAG: u8 = 0x30;
+const OID_TAG: u8 = 0x06;
+const BOOLEAN_TAG: u8 = 0x01;
+const OCTET_STRING_TAG: u8 = 0x04;
+
+/// Tag of the extensions field of the TBSCertificate ([3] EXPLICIT).
+const EXTENSIONS_TAG: u8 = 0xa3;
+
+/// Tag of the dNSName in the GeneralName choice.
+const DNS_NAME_TAG: u8 = 0x82;
+
+/// Extracts the dNSName entries of the subjectAltName extension from a
+/// DER certificate, following its structure:
+///
+/// ```text
+/// Certificate ::= SEQUENCE { tbsCertificate, ... }
+/// TBSCertificate ::= SEQUENCE { ..., extensions [3] EXPLICIT Extensions }
+/// Extension ::= SEQUENCE { extnID, critical BOOLEAN DEFAULT FALSE,
+///                          extnValue OCTET STRING }
+/// ```
+pub fn certificate_names(der: &[u8]) -> Result<Vec<String>, String> {
+    let invalid = || "Invalid certificate".to_string();
+
+    let cert = read_expected(der, SEQUENCE_TAG).ok_or_else(invalid)?;
+    let tbs = read_expected(cert, SEQUENCE_TAG).ok_or_else(invalid)?;
+
+    // version 1 certificates don't have extensions
+    let extensions = match find_tlv(tbs, EXTENSIONS_TAG) {
+        Some(extensions) => extensions,
+        None => return Ok(Vec::new()),
+    };
+    let mut extensions =
+        read_expected(extensions, SEQUENCE_TAG).ok_or_else(invalid)?;
+
+    while let Some((tag, extension, rest)) = read_tlv(extensions) {
+        extensions = rest;
+        if tag != SEQUENCE_TAG {
+            return Err(invalid());
+        }
+
+        let (tag, oid, mut fields) = read_tlv(extension).ok_or_else(invalid)?;
+        if tag != OID_TAG || oid != SAN_OID {
+            continue;
+        }
+
+        if let Some((BOOLEAN_TAG, _, rest)) = read_tlv(fields) {
+            fields = rest;
+        }
+
+        let value =
+            read_expected(fields, OCTET_STRING_TAG).ok_or_else(invalid)?;
+        let general_names =
+            read_expected(value, SEQUENCE_TAG).ok_or_else(invalid)?;
+        return Ok(dns_names(general_names));
+    }
+
+    return Ok(Vec::new());
+}
+
+/// Returns the dNSName entries of a sequence of GeneralName.
+fn dns_names(mut general_names: &[u8]) -> Vec<String> {
+    let mut names = Vec::new();
+    while let Some((tag, value, rest)) = read_tlv(general_names) {
+        if tag == DNS_NAME_TAG {
+            if let Ok(name) = std::str::from_utf8(value) {
+                names.push(name.to_lowercase());
+            }
+        }
+        general_names = rest;
+    }
+    return names;
+}
+
+/// Returns the value of the first element of a sequence with the tag.
+fn find_tlv(mut data: &[u8], tag: u8) -> Option<&[u8]> {
+    while let Some((element_tag, value, rest)) = read_tlv(data) {
+        if element_tag == tag {
+            return Some(value);
+        }
+        data = rest;
+    }
+    return None;
+}
+
+/// Returns the value of the element at the start of the data, if it has
+/// the expected tag.
+fn read_expected(data: &[u8], tag: u8) -> Option<&[u8]> {
+    match read_tlv(data)? {
+        (element_tag, value, _) if element_tag == tag => Some(value),
+        _ => None,
+    }
+}
+
+/// Reads a DER element. Returns its tag, its value and the remaining data.
+fn read_tlv(data: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let tag = *data.first()?;
+    let first = *data.get(1)? as usize;
+
+    let (len, start) = if first < 0x80 {
+        (first, 2)
+    } else {
+        let octets = first & 0x7f;
+        if octets == 0 || octets > 4 {
+            return None;
+        }
+        let mut len = 0;
+        for b in data.get(2..2 + octets)? {
+            len = (len << 8) | *b as usize;
+        }
+        (len, 2 + octets)
+    };
+
+    let value = data.get(start..start.checked_add(len)?)?;
+    return Some((tag, value, &data[start + len..]));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use openssl::asn1::Asn1Time;
+    use openssl::hash::MessageDigest;
+    use openssl::pkey::PKey;
+    use openssl::rsa::Rsa;
+    use openssl::x509::extension::{BasicConstraints, SubjectAlternativeName};
+    use openssl::x509::{X509Builder, X509NameBuilder, X509};
+
+    /// Builds a self-signed certificate with the given common name and
+    /// subject alternative names.
+    fn new_certificate(cn: &str, san: Option<SubjectAlternativeName>) -> X509 {
+        let key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+        let mut name = X509NameBuilder::new().unwrap();
+        name.append_entry_by_text("CN", cn).unwrap();
+        let name = name.build();
+
+        let mut builder = X509Builder::new().unwrap();
+        builder.set_version(2).unwrap();
+        builder.set_subject_name(&name).unwrap();
+        builder.set_issuer_name(&name).unwrap();
+        builder.set_pubkey(&key).unwrap();
+        builder
+            .set_not_before(&Asn1Time::days_from_now(0).unwrap())
+            .unwrap();
+        builder
+            .set_not_after(&Asn1Time::days_from_now(1).unwrap())
+            .unwrap();
+
+        let constraints = BasicConstraints::new().critical().build().unwrap();
+        builder.append_extension(constraints).unwrap();
+        if let Some(san) = san {
+            let san = san.build(&builder.x509v3_context(None, None)).unwrap();
+            builder.append_extension(san).unwrap();
+        }
+
+        builder.sign(&key, MessageDigest::sha256()).unwrap();
+        return builder.build();
+    }
+
+    #[test]
+    fn extract_san_dns_names() {
+        let mut san = SubjectAlternativeName::new();
+        san.critical()
+            .dns("Mail.contoso.local")
+            .ip("10.0.0.1")
+            .email("admin@contoso.local")
+            .dns("*.owa.contoso.local");
+        let cert = new_certificate("mail", Some(san));
+
+        let names = certificate_names(&cert.to_der().unwrap()).unwrap();
+        assert_eq!(names, vec!["mail.contoso.local", "*.owa.contoso.local"]);
+    }
+
+    #[test]
+    fn ignore_san_oid_out_of_extensions() {
+        // the encoded OID of the SAN in the subject, without SAN extension
+        let cert = new_certificate("\u{6}\u{3}U\u{1d}\u{11}\u{4}", None);
+        let names = certificate_names(&cert.to_der().unwrap()).unwrap();
+        assert_eq!(names.len(), 0);
+    }
+
+    #[test]
+    fn reject_invalid_certificates() {
+        assert!(certificate_names(&[]).is_err());
+        assert!(certificate_names(&[0x30, 0x82, 0xff]).is_err());
+        assert!(certificate_names(b"-----BEGIN CERTIFICATE-----").is_err());
+    }
+}
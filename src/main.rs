@@ -1,29 +1,61 @@
 mod args;
 mod auth;
 mod challenge;
+mod dedup;
+mod diff;
+mod discover;
 mod dns;
+mod error;
 mod http;
+mod pcap;
+mod pool;
 mod printer;
+mod range;
 mod readin;
 mod smb;
 
+use crate::args::DiffArgs;
 use crate::args::HttpArgs;
+use crate::args::OutputArgs;
+use crate::args::ParseArgs;
+use crate::args::PcapArgs;
 use crate::args::SmbArgs;
+use crate::auth::decode_ntlm_msg;
+use crate::auth::NtlmMsg;
 use crate::challenge::Challenge;
+use crate::challenge::ChallengeTarget;
+use crate::challenge::Failure;
+use crate::challenge::Host;
+use crate::dedup::Dedup;
+use crate::dedup::DEDUP_BATCH;
+use crate::discover::Discovered;
+use crate::discover::Discovery;
+use crate::dns::DnsResolver;
+use crate::error::Error;
 use crate::http::challenge_http;
+use crate::http::http_scan_targets;
+use crate::http::url_ips;
 use crate::http::HttpOptions;
+use crate::pcap::TcpFlows;
+use crate::pool::BoundedPool;
 use crate::printer::Output;
+use crate::range::{Exclusions, IpRange, Scope};
 use crate::smb::fetch_ntlm_challenge_smb;
+use crate::smb::smb_scan_targets;
+use crate::smb::split_host_port;
 use crate::smb::SmbOptions;
+use crate::smb::SMB_PORT;
 use args::Args;
-use ipnet::IpNet;
-use log::{error, warn};
-use readin::read_inputs;
+use log::{error, info, warn};
+use readin::{read_inputs, read_targets};
+use std::collections::HashSet;
+use std::net::IpAddr;
+use std::process;
 use std::thread;
 use stderrlog;
 
 use std::sync::mpsc::{channel, Receiver, Sender};
-use threadpool::ThreadPool;
+use std::sync::Arc;
 
 fn init_log(verbosity: usize) {
     stderrlog::new()
@@ -33,44 +65,343 @@ fn init_log(verbosity: usize) {
         .unwrap();
 }
 
+fn new_output(args: OutputArgs) -> Output {
+    match Output::new(args) {
+        Ok(out) => out,
+        Err(err) => {
+            error!("{}", err);
+            process::exit(1);
+        }
+    }
+}
+
+fn new_exclusions(hosts: &Vec<String>, files: &Vec<String>) -> Exclusions {
+    match Exclusions::new(hosts, files) {
+        Ok(exclusions) => exclusions,
+        Err(err) => {
+            error!("{}", err);
+            process::exit(1);
+        }
+    }
+}
+
+fn new_resolver(
+    servers: &[String],
+    tcp: bool,
+    search: &[String],
+) -> DnsResolver {
+    match DnsResolver::new(servers, tcp, search) {
+        Ok(resolver) => resolver,
+        Err(err) => {
+            error!("{}", err);
+            process::exit(1);
+        }
+    }
+}
+
 fn main() {
     let args = Args::parse_args();
 
     match args {
         Args::Smb(a) => smb_main(a),
         Args::Http(a) => http_main(a),
+        Args::Parse(a) => parse_main(a),
+        Args::Pcap(a) => pcap_main(a),
+        Args::Diff(a) => diff_main(a),
     }
 }
 
 fn http_main(args: HttpArgs) {
     init_log(args.verbosity);
 
+    let resolver =
+        new_resolver(&args.dns_servers, args.dns_tcp, &args.dns_search);
+    let scope = new_scope(args.recursive, &args.scope);
     let options = HttpOptions {
         timeout: args.timeout,
+        tls_names: args.recursive,
+        all_ips: args.all_ips,
+        resolver,
+        exclusions: Arc::new(new_exclusions(
+            &args.exclude,
+            &args.exclude_files,
+        )),
+        scope: scope.clone(),
     };
 
-    let out = Output::new(args.json);
-    let pool = ThreadPool::new(args.workers);
+    // discovered hosts are scanned with SMB, where domain controllers
+    // offer NTLM
+    let smb_options = match args.discover || args.recursive {
+        true => Some(SmbOptions {
+            timeout: args.timeout,
+            port: SMB_PORT,
+            all_ips: false,
+            resolver: options.resolver.clone(),
+        }),
+        false => None,
+    };
+
+    let discovery =
+        new_discovery(&options.resolver, args.discover, scope.clone());
+    let dns_check = Some(options.resolver.clone()).filter(|_| args.dns_check);
+
+    let out = new_output(args.output);
+    let pool = BoundedPool::new(args.workers);
     let (sc, rc) = channel();
+    let (sd, rd) = channel();
+    let ctx = ScanContext::new(dns_check, discovery, sc, sd);
 
     let out_thread = thread::spawn(move || {
         handle_output(out, rc);
     });
 
-    for url in read_inputs(args.urls, true, true) {
-        let sc = sc.clone();
-        pool.execute(move || match challenge_http(&url, options) {
-            Ok(challenge) => {
-                sc.send(challenge).expect("Error sending HTTP challenge");
+    let mut urls = HashSet::new();
+    http_dispatch(
+        &pool,
+        &ctx,
+        &options,
+        None,
+        &mut urls,
+        read_targets(args.urls, http_scan_targets),
+    );
+
+    // names found in the HTTP responses are requested with HTTP and the
+    // rest of the discovered hosts are scanned with SMB
+    let mut dedup = smb_options.as_ref().map(Dedup::new);
+    scan_discovered(&pool, &rd, args.max_depth, |discovered| {
+        let (hosts, new_urls) = split_discovered(discovered);
+        http_dispatch(
+            &pool,
+            &ctx,
+            &options,
+            scope.clone(),
+            &mut urls,
+            new_urls.into_iter(),
+        );
+        if let (Some(smb_options), Some(dedup)) = (&smb_options, &mut dedup) {
+            smb_dispatch(
+                &pool,
+                &ctx,
+                smb_options,
+                &options.exclusions,
+                scope.as_deref(),
+                dedup,
+                hosts,
+            );
+        }
+    });
+
+    pool.join();
+    drop(ctx);
+    out_thread.join().expect("Error joining output thread");
+}
+
+/// Sends to the workers the URLs that weren't requested before and are not
+/// excluded. If the scope is given, only its IPs are requested.
+fn http_dispatch<I>(
+    pool: &BoundedPool,
+    ctx: &ScanContext,
+    options: &HttpOptions,
+    scope: Option<Arc<Scope>>,
+    urls: &mut HashSet<String>,
+    targets: I,
+) where
+    I: Iterator<Item = String>,
+{
+    for url in targets {
+        // the URL is normalized to detect duplicates, like a default port
+        let url = match url::Url::parse(&url) {
+            Ok(parsed) => parsed.to_string(),
+            Err(_) => url,
+        };
+        if !urls.insert(url.clone()) {
+            info!("Skipping {}, already scanned", url);
+            continue;
+        }
+
+        let host = url::Url::parse(&url)
+            .ok()
+            .and_then(|u| u.host_str().map(|h| h.to_string()));
+        if let Some(host) = &host {
+            if options.exclusions.contains_host(host) {
+                info!("Excluding {}", url);
+                continue;
+            }
+            ctx.add_target(host);
+        }
+        ctx.add_url(&url);
+
+        let ctx = ctx.clone();
+        let options = options.clone();
+        let scope = scope.clone();
+        pool.execute(move || {
+            http_execute(
+                &ctx,
+                &options,
+                scope.as_deref(),
+                &url,
+                host.as_deref().unwrap_or(""),
+            );
+        });
+    }
+}
+
+/// Requests the URL, once or for each of its IPs, in a worker, since the
+/// names are resolved here to not wait for them. The requests are pinned
+/// to the resolved IPs that are not excluded and, if given, in scope.
+fn http_execute(
+    ctx: &ScanContext,
+    options: &HttpOptions,
+    scope: Option<&Scope>,
+    url: &str,
+    host: &str,
+) {
+    let ips = match url_ips(url, &options.resolver) {
+        Ok(ips) => ips,
+        Err(err) => {
+            ctx.send(Err(err), url);
+            return;
+        }
+    };
+
+    let mut ips: Vec<IpAddr> = ips
+        .into_iter()
+        .filter(|ip| !options.exclusions.contains_ip(ip))
+        .collect();
+    if ips.len() == 0 {
+        info!("Excluding {}", url);
+        return;
+    }
+
+    if let Some(scope) = scope {
+        ips = scope.filter_ips(host, ips);
+        if ips.len() == 0 {
+            info!("Skipping {}, out of scope", url);
+            return;
+        }
+    }
+
+    if !options.all_ips {
+        ctx.send(challenge_http(url, options, &ips), url);
+        return;
+    }
+
+    // each IP is requested with the URL, and its results are told apart by
+    // their address
+    for ip in ips {
+        let target = format!("{} ({})", url, ip);
+        ctx.send(challenge_http(url, options, &[ip]), &target);
+    }
+}
+
+/// Splits the discovered hosts into the names to scan with SMB and the URLs
+/// to request with HTTP.
+fn split_discovered(discovered: Vec<Discovered>) -> (Vec<String>, Vec<String>) {
+    let mut hosts = Vec::new();
+    let mut urls = Vec::new();
+    for host in discovered {
+        match host {
+            Discovered::Host(host) => hosts.push(host),
+            Discovered::Url(url) => urls.push(url),
+        }
+    }
+    return (hosts, urls);
+}
+
+fn parse_main(args: ParseArgs) {
+    init_log(args.verbosity);
+
+    let mut out = new_output(args.output);
+
+    for (i, input) in read_inputs(args.messages, true, true).enumerate() {
+        let target = ChallengeTarget::Input(i + 1);
+        match decode_ntlm_msg(&input) {
+            Ok(NtlmMsg::Negotiate(neg)) => out.add_negotiate(&target, &neg),
+            Ok(NtlmMsg::Challenge(ch)) => {
+                let mut challenge = Challenge::new(target, ch);
+                challenge.received = None;
+                out.add(challenge);
+            }
+            Ok(NtlmMsg::Authenticate(auth)) => {
+                out.add_authenticate(&target, &auth)
             }
             Err(err) => {
-                warn!("{}", err);
+                warn!("{}: {}", target, err);
             }
-        });
+        }
     }
 
-    drop(sc);
-    out_thread.join().expect("Error joining output thread");
+    for err in out.finish() {
+        error!("{}", err);
+    }
+}
+
+fn pcap_main(args: PcapArgs) {
+    init_log(args.verbosity);
+
+    let mut flows = TcpFlows::new();
+    for path in args.files.iter() {
+        if let Err(err) = flows.read_pcap(path) {
+            error!("{}", err);
+        }
+    }
+
+    let mut out = new_output(args.output);
+    for challenge in flows.extract_challenges() {
+        out.add(challenge);
+    }
+
+    for err in out.finish() {
+        error!("{}", err);
+    }
+}
+
+fn diff_main(args: DiffArgs) {
+    init_log(args.verbosity);
+
+    let scans = match &args.db {
+        Some(db) => load_db_scans(db, &args.old, &args.new),
+        None => load_json_scans(
+            args.old.as_ref().unwrap(),
+            args.new.as_ref().unwrap(),
+        ),
+    };
+
+    match scans {
+        Ok((old, new)) => diff::print_diff(&old, &new),
+        Err(err) => {
+            error!("{}", err);
+            process::exit(1);
+        }
+    }
+}
+
+fn load_json_scans(
+    old: &str,
+    new: &str,
+) -> Result<(diff::Scan, diff::Scan), String> {
+    return Ok((diff::load_json(old)?, diff::load_json(new)?));
+}
+
+fn load_db_scans(
+    db: &str,
+    old: &Option<String>,
+    new: &Option<String>,
+) -> Result<(diff::Scan, diff::Scan), String> {
+    let (old_id, new_id) = match (old, new) {
+        (Some(old), Some(new)) => (parse_scan_id(old)?, parse_scan_id(new)?),
+        (None, None) => diff::last_db_scans(db)?,
+        _ => return Err("Both scan IDs must be specified".to_string()),
+    };
+
+    return Ok((
+        diff::load_db_scan(db, old_id)?,
+        diff::load_db_scan(db, new_id)?,
+    ));
+}
+
+fn parse_scan_id(id: &str) -> Result<i64, String> {
+    return id.parse().map_err(|_| format!("Invalid scan ID '{}'", id));
 }
 
 fn smb_main(args: SmbArgs) {
@@ -78,62 +409,283 @@ fn smb_main(args: SmbArgs) {
 
     let options = SmbOptions {
         timeout: args.timeout,
-        port: 445,
+        port: SMB_PORT,
+        all_ips: args.all_ips,
+        resolver: new_resolver(
+            &args.dns_servers,
+            args.dns_tcp,
+            &args.dns_search,
+        ),
     };
 
-    let out = Output::new(args.json);
-    let pool = ThreadPool::new(args.workers);
+    let scope = new_scope(args.recursive, &args.scope);
+    let discovery =
+        new_discovery(&options.resolver, args.discover, scope.clone());
+    let dns_check = Some(options.resolver.clone()).filter(|_| args.dns_check);
+
+    let exclusions = new_exclusions(&args.exclude, &args.exclude_files);
+    let out = new_output(args.output);
+    let pool = BoundedPool::new(args.workers);
     let (sc, rc) = channel();
+    let (sd, rd) = channel();
+    let ctx = ScanContext::new(dns_check, discovery, sc, sd);
 
     let out_thread = thread::spawn(move || {
         handle_output(out, rc);
     });
 
-    for target in read_inputs(args.targets, true, true) {
-        match target.parse::<IpNet>() {
-            Ok(net) => {
-                for ip in net.hosts() {
-                    let sc = sc.clone();
-                    pool.execute(move || {
-                        smb_do(ip.to_string(), options, sc);
-                    });
-                }
+    let mut dedup = Dedup::new(&options);
+    let mut targets = read_targets(args.targets, smb_scan_targets);
+    loop {
+        let batch: Vec<String> = targets.by_ref().take(DEDUP_BATCH).collect();
+        if batch.len() == 0 {
+            break;
+        }
+        smb_dispatch(
+            &pool,
+            &ctx,
+            &options,
+            &exclusions,
+            None,
+            &mut dedup,
+            batch,
+        );
+    }
+
+    // only names are discovered from SMB challenges
+    scan_discovered(&pool, &rd, args.max_depth, |discovered| {
+        smb_dispatch(
+            &pool,
+            &ctx,
+            &options,
+            &exclusions,
+            scope.as_deref(),
+            &mut dedup,
+            split_discovered(discovered).0,
+        )
+    });
+
+    pool.join();
+    drop(ctx);
+    out_thread.join().expect("Error joining output thread");
+}
+
+/// Sends to the workers the hosts of the SMB targets, after removing the
+/// excluded and duplicated ones, and the ones out of scope if given.
+fn smb_dispatch(
+    pool: &BoundedPool,
+    ctx: &ScanContext,
+    options: &SmbOptions,
+    exclusions: &Exclusions,
+    scope: Option<&Scope>,
+    dedup: &mut Dedup,
+    targets: Vec<String>,
+) {
+    let targets = targets
+        .into_iter()
+        .filter(|target| {
+            if IpRange::parse(target).is_some() {
+                return true;
             }
-            Err(_) => {
-                let sc = sc.clone();
-                pool.execute(move || {
-                    smb_do(target, options, sc);
-                });
+
+            let host = split_host_port(target, 0).0;
+            if exclusions.contains_host(&host) {
+                info!("Excluding {}", target);
+                return false;
             }
-        };
+            ctx.add_target(&host);
+            return true;
+        })
+        .collect();
+
+    dedup.dispatch(
+        targets,
+        exclusions,
+        scope,
+        |host, port| smb_execute(pool, ctx, options, host, port),
+        |target, err| ctx.send(Err(err), &target),
+    );
+}
+
+fn smb_execute(
+    pool: &BoundedPool,
+    ctx: &ScanContext,
+    options: &SmbOptions,
+    host: Host,
+    port: u16,
+) {
+    let ctx = ctx.clone();
+    let timeout = options.timeout;
+
+    // name of the target in case of failure
+    let mut target = match host.names.first() {
+        Some(name) => name.clone(),
+        None => host.ip.to_string(),
+    };
+    if port != options.port {
+        target = format!("{}:{}", target, port);
     }
 
-    drop(sc);
-    out_thread.join().expect("Error joining output thread");
+    pool.execute(move || {
+        ctx.send(fetch_ntlm_challenge_smb(host, port, timeout), &target);
+    });
 }
 
-fn smb_do(host: String, options: SmbOptions, sender: Sender<Challenge>) {
-    match fetch_ntlm_challenge_smb(host, options) {
-        Ok(challenge) => {
-            sender.send(challenge).expect("Error sending SMB challenge");
+/// Scans the hosts discovered by the jobs, until no new hosts appear or
+/// the maximum depth is reached. Each round scans the hosts discovered in
+/// the previous one.
+fn scan_discovered<F>(
+    pool: &BoundedPool,
+    discovered: &Receiver<Discovered>,
+    max_depth: usize,
+    mut dispatch: F,
+) where
+    F: FnMut(Vec<Discovered>),
+{
+    for depth in 1..=max_depth {
+        pool.wait();
+        let hosts: Vec<Discovered> = discovered.try_iter().collect();
+        if hosts.len() == 0 {
+            return;
         }
-        Err(err) => {
-            warn!("{}", err);
+
+        info!(
+            "Scanning {} discovered hosts (depth {})",
+            hosts.len(),
+            depth
+        );
+        dispatch(hosts);
+    }
+
+    pool.wait();
+    let skipped = discovered.try_iter().count();
+    if skipped != 0 {
+        info!("Skipping {} discovered hosts, max depth reached", skipped);
+    }
+}
+
+/// Scope of the recursive mode, which is checked when the names are
+/// followed and again for each IP of the discovered hosts.
+fn new_scope(recursive: bool, scope: &[String]) -> Option<Arc<Scope>> {
+    if !recursive {
+        return None;
+    }
+    return Some(Arc::new(Scope::new(scope)));
+}
+
+fn new_discovery(
+    resolver: &DnsResolver,
+    srv: bool,
+    follow: Option<Arc<Scope>>,
+) -> Option<Discovery> {
+    if !srv && follow.is_none() {
+        return None;
+    }
+
+    return Some(Discovery::new(resolver.clone(), srv, follow));
+}
+
+/// State shared by the jobs of a scan, to process the retrieved challenges
+/// and send them to the output.
+#[derive(Clone)]
+struct ScanContext {
+    dns_check: Option<DnsResolver>,
+    discovery: Option<Discovery>,
+    results: Sender<Result<Challenge, Failure>>,
+    discovered: Sender<Discovered>,
+}
+
+impl ScanContext {
+    fn new(
+        dns_check: Option<DnsResolver>,
+        discovery: Option<Discovery>,
+        results: Sender<Result<Challenge, Failure>>,
+        discovered: Sender<Discovered>,
+    ) -> Self {
+        return Self {
+            dns_check,
+            discovery,
+            results,
+            discovered,
+        };
+    }
+
+    /// Registers a host given as target, to not scan it again if it is
+    /// discovered.
+    fn add_target(&self, host: &str) {
+        if let Some(discovery) = &self.discovery {
+            discovery.add_target(host);
         }
     }
+
+    /// Registers an URL given as target, to not request it again if one of
+    /// its names is found.
+    fn add_url(&self, url: &str) {
+        if let Some(discovery) = &self.discovery {
+            discovery.add_url(url);
+        }
+    }
+
+    /// Checks the DNS records and discovers new hosts from the challenge,
+    /// if requested, and sends the result to the output.
+    fn send(&self, result: Result<Challenge, Error>, target: &str) {
+        let result = match result {
+            Ok(ch) => {
+                let ch = check_dns(ch, &self.dns_check);
+                if let Some(discovery) = &self.discovery {
+                    for host in discovery.discover(&ch) {
+                        self.discovered
+                            .send(host)
+                            .expect("Error sending discovered host");
+                    }
+                }
+                Ok(ch)
+            }
+            Err(err) => Err(Failure::new(target.to_string(), err)),
+        };
+
+        self.results.send(result).expect("Error sending challenge");
+    }
+}
+
+/// Compares the DNS records of the host with the DNS computer name of the
+/// challenge, if the check is requested.
+fn check_dns(mut ch: Challenge, resolver: &Option<DnsResolver>) -> Challenge {
+    let resolver = match resolver {
+        Some(resolver) => resolver,
+        None => return ch,
+    };
+
+    let ip = match ch.ip() {
+        Some(ip) => ip,
+        None => return ch,
+    };
+
+    let name = match ch.challenge.dns_computer_name() {
+        Some(name) if name.len() != 0 => name.clone(),
+        _ => return ch,
+    };
+
+    match resolver.check(ip, &name) {
+        Ok(check) => ch.dns = Some(check),
+        Err(err) => warn!("{}: {}", ch.target, err),
+    }
+
+    return ch;
 }
 
-fn handle_output(mut out: Output, rc: Receiver<Challenge>) {
+fn handle_output(mut out: Output, rc: Receiver<Result<Challenge, Failure>>) {
     loop {
         match rc.recv() {
-            Ok(ch) => out.add(ch),
+            Ok(Ok(ch)) => out.add(ch),
+            Ok(Err(failure)) => out.add_failure(failure),
             Err(_) => {
                 break;
             }
         }
     }
 
-    if let Err(err) = out.finish() {
+    for err in out.finish() {
         error!("{}", err);
     }
 }
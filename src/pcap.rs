@@ -0,0 +1,700 @@
+use crate::auth::{
+    decode_ntlm_msg, parse_ntlm_msg, NtlmMsg, NTLMSSP_SIGNATURE,
+    NTLM_MSG_CHALLENGE,
+};
+use crate::challenge::{Challenge, Host, Protocol};
+use chrono::{TimeZone, Utc};
+use etherparse::{InternetSlice, SlicedPacket, TransportSlice};
+use log::{debug, info};
+use ntlm::time::NtlmTime;
+use ntlm::ChallengeMsg;
+use pcap_parser::{create_reader, Block, Linktype, PcapBlockOwned, PcapError};
+use std::collections::HashMap;
+use std::fs::File;
+use std::net::{IpAddr, SocketAddr};
+
+const READER_BUFFER_SIZE: usize = 1 << 20;
+
+/// Maximum data kept of each flow. The challenge is sent at the beginning
+/// of the connection, so the rest of the flow is not needed.
+const MAX_FLOW_SIZE: usize = 64 * 1024;
+
+/// HTTP headers used by servers to send the NTLM challenge.
+const HTTP_CHALLENGE_HEADERS: &'static [&'static str] =
+    &["www-authenticate:", "proxy-authenticate:"];
+
+/// Identifies the TCP flow in one direction: (source, destination).
+type FlowKey = (SocketAddr, SocketAddr);
+
+struct Segment {
+    seq: u32,
+    time: NtlmTime,
+    data: Vec<u8>,
+}
+
+/// Data of a TCP connection in one direction, kept until a challenge is
+/// found in it or the connection ends.
+#[derive(Default)]
+struct Flow {
+    /// Sequence number of the first byte of data, known when the SYN of
+    /// the connection is captured.
+    start_seq: Option<u32>,
+
+    segments: Vec<Segment>,
+    size: usize,
+
+    /// A challenge could be split in the next segments.
+    incomplete: bool,
+
+    /// The flow was already searched, so the rest of its data is ignored.
+    done: bool,
+}
+
+impl Flow {
+    fn new(start_seq: Option<u32>) -> Self {
+        return Self {
+            start_seq,
+            ..Default::default()
+        };
+    }
+}
+
+/// Challenge found in a flow, sent by the server of `address`.
+struct Found {
+    time: NtlmTime,
+    address: SocketAddr,
+    protocol: Option<Protocol>,
+    challenge: ChallengeMsg,
+}
+
+/// Interface parameters required to decode the packets of a capture.
+struct Interface {
+    linktype: Linktype,
+    ts_offset: u64,
+    ts_resolution: u64,
+}
+
+/// Collection of the TCP segments read from captures, grouped by flow, in
+/// order to reassemble the streams and search NTLM challenges in them.
+/// Flows are dropped once they are searched, so only the connections in
+/// progress are kept in memory, up to `MAX_FLOW_SIZE` each.
+pub struct TcpFlows {
+    flows: HashMap<FlowKey, Flow>,
+
+    /// First challenge sent by each server.
+    servers: HashMap<IpAddr, Found>,
+}
+
+impl TcpFlows {
+    pub fn new() -> Self {
+        return Self {
+            flows: HashMap::new(),
+            servers: HashMap::new(),
+        };
+    }
+
+    /// Read the TCP segments of a pcap or pcapng file.
+    pub fn read_pcap(&mut self, path: &str) -> Result<(), String> {
+        let file = File::open(path)
+            .map_err(|e| format!("Error opening '{}': {}", path, e))?;
+
+        let mut reader = create_reader(READER_BUFFER_SIZE, file)
+            .map_err(|e| format!("Error reading '{}': {:?}", path, e))?;
+
+        let mut interfaces = Vec::new();
+        let mut packets = 0;
+
+        loop {
+            match reader.next() {
+                Ok((offset, block)) => {
+                    match block {
+                        PcapBlockOwned::LegacyHeader(header) => {
+                            let ts_resolution =
+                                if header.is_nanosecond_precision() {
+                                    1_000_000_000
+                                } else {
+                                    1_000_000
+                                };
+                            interfaces = vec![Interface {
+                                linktype: header.network,
+                                ts_offset: 0,
+                                ts_resolution,
+                            }];
+                        }
+                        PcapBlockOwned::Legacy(packet) => {
+                            if let Some(iface) = interfaces.first() {
+                                let time = new_time(
+                                    packet.ts_sec,
+                                    packet.ts_usec,
+                                    iface.ts_resolution,
+                                );
+                                self.add_packet(
+                                    iface.linktype,
+                                    time,
+                                    packet.data,
+                                );
+                                packets += 1;
+                            }
+                        }
+                        PcapBlockOwned::NG(Block::SectionHeader(_)) => {
+                            interfaces.clear();
+                        }
+                        PcapBlockOwned::NG(Block::InterfaceDescription(
+                            idb,
+                        )) => {
+                            interfaces.push(Interface {
+                                linktype: idb.linktype,
+                                ts_offset: idb.ts_offset(),
+                                ts_resolution: idb
+                                    .ts_resolution()
+                                    .unwrap_or(1_000_000),
+                            });
+                        }
+                        PcapBlockOwned::NG(Block::EnhancedPacket(epb)) => {
+                            if let Some(iface) =
+                                interfaces.get(epb.if_id as usize)
+                            {
+                                let (secs, frac) = epb.decode_ts(
+                                    iface.ts_offset,
+                                    iface.ts_resolution,
+                                );
+                                let time =
+                                    new_time(secs, frac, iface.ts_resolution);
+                                let data = &epb.data[..(epb.caplen as usize)
+                                    .min(epb.data.len())];
+                                self.add_packet(iface.linktype, time, data);
+                                packets += 1;
+                            }
+                        }
+                        PcapBlockOwned::NG(Block::SimplePacket(spb)) => {
+                            // simple packets have no timestamp
+                            if let Some(iface) = interfaces.first() {
+                                self.add_packet(
+                                    iface.linktype,
+                                    ntlm::time::now(),
+                                    spb.data,
+                                );
+                                packets += 1;
+                            }
+                        }
+                        _ => {}
+                    }
+                    reader.consume(offset);
+                }
+                Err(PcapError::Eof) => break,
+                Err(PcapError::Incomplete) => {
+                    reader.refill().map_err(|e| {
+                        format!("Error reading '{}': {:?}", path, e)
+                    })?;
+                }
+                Err(e) => {
+                    return Err(format!("Error reading '{}': {:?}", path, e));
+                }
+            }
+        }
+
+        info!("Read {} packets from '{}'", packets, path);
+
+        return Ok(());
+    }
+
+    fn add_packet(&mut self, linktype: Linktype, time: NtlmTime, data: &[u8]) {
+        let packet = match slice_packet(linktype, data) {
+            Some(packet) => packet,
+            None => return,
+        };
+
+        let (src_ip, dst_ip) = match &packet.ip {
+            Some(InternetSlice::Ipv4(ip, _)) => (
+                IpAddr::from(ip.source_addr()),
+                IpAddr::from(ip.destination_addr()),
+            ),
+            Some(InternetSlice::Ipv6(ip, _)) => (
+                IpAddr::from(ip.source_addr()),
+                IpAddr::from(ip.destination_addr()),
+            ),
+            None => return,
+        };
+
+        let tcp = match &packet.transport {
+            Some(TransportSlice::Tcp(tcp)) => tcp,
+            _ => return,
+        };
+
+        let key = (
+            SocketAddr::new(src_ip, tcp.source_port()),
+            SocketAddr::new(dst_ip, tcp.destination_port()),
+        );
+
+        // a new connection, which could reuse the port of a previous one
+        if tcp.syn() {
+            self.finish_flow(&key);
+            let start_seq = tcp.sequence_number().wrapping_add(1);
+            self.flows.insert(key, Flow::new(Some(start_seq)));
+        }
+
+        if packet.payload.len() != 0 {
+            self.add_segment(
+                key,
+                Segment {
+                    seq: tcp.sequence_number(),
+                    time,
+                    data: packet.payload.to_vec(),
+                },
+            );
+        }
+
+        if tcp.fin() || tcp.rst() {
+            self.finish_flow(&key);
+        }
+    }
+
+    fn add_segment(&mut self, key: FlowKey, segment: Segment) {
+        let flow = self.flows.entry(key).or_insert_with(|| Flow::new(None));
+        if flow.done {
+            return;
+        }
+
+        let has_ntlm = contains_ntlm(&segment.data);
+        flow.size += segment.data.len();
+        flow.segments.push(segment);
+
+        if flow.size >= MAX_FLOW_SIZE {
+            debug!("Flow {} -> {} exceeds the maximum size", key.0, key.1);
+            self.search_flow(&key);
+            self.release_flow(&key);
+        } else if (has_ntlm || flow.incomplete) && self.search_flow(&key) {
+            self.release_flow(&key);
+        }
+    }
+
+    /// Searches the challenges of the flow. Returns true if any was found.
+    fn search_flow(&mut self, key: &FlowKey) -> bool {
+        let flow = match self.flows.get_mut(key) {
+            Some(flow) => flow,
+            None => return false,
+        };
+
+        let stream =
+            TcpStreamData::reassemble(&mut flow.segments, flow.start_seq);
+        let (challenges, incomplete) = stream.find_challenges();
+        flow.incomplete = incomplete;
+
+        let (src, dst) = key;
+        let found = challenges.len() != 0;
+        for (time, protocol, challenge) in challenges {
+            debug!("NTLM challenge found in {} -> {}", src, dst);
+            self.add_found(Found {
+                time,
+                address: *src,
+                protocol: protocol.or(port_protocol(src.port())),
+                challenge,
+            });
+        }
+
+        return found;
+    }
+
+    /// Releases the data of the flow, ignoring the rest of it.
+    fn release_flow(&mut self, key: &FlowKey) {
+        if let Some(flow) = self.flows.get_mut(key) {
+            flow.segments = Vec::new();
+            flow.size = 0;
+            flow.incomplete = false;
+            flow.done = true;
+        }
+    }
+
+    /// Searches the challenges of a flow that ended and removes it.
+    fn finish_flow(&mut self, key: &FlowKey) {
+        let done = match self.flows.get(key) {
+            Some(flow) => flow.done,
+            None => return,
+        };
+
+        if !done {
+            self.search_flow(key);
+        }
+        self.flows.remove(key);
+    }
+
+    /// Keeps the challenge if it is the first one sent by the server.
+    fn add_found(&mut self, found: Found) {
+        match self.servers.get(&found.address.ip()) {
+            Some(first) if first.time <= found.time => {}
+            _ => {
+                self.servers.insert(found.address.ip(), found);
+            }
+        }
+    }
+
+    /// Searches the flows that didn't end in the captures and retrieves
+    /// the first NTLM challenge sent by each server.
+    pub fn extract_challenges(mut self) -> Vec<Challenge> {
+        let keys: Vec<FlowKey> = self.flows.keys().cloned().collect();
+        for key in keys {
+            self.finish_flow(&key);
+        }
+
+        let mut found: Vec<Found> = self.servers.into_values().collect();
+        found.sort_by_key(|f| f.time);
+
+        let mut challenges = Vec::new();
+        for f in found {
+            let mut challenge = Challenge::new(
+                Host::new(f.address.ip(), Vec::new()).into(),
+                f.challenge,
+            );
+            challenge.address = Some(f.address);
+            challenge.protocol = f.protocol;
+            challenge.received = Some(f.time);
+            challenges.push(challenge);
+        }
+
+        return challenges;
+    }
+}
+
+/// Data of a reassembled TCP stream, with the time each piece of data was
+/// captured.
+struct TcpStreamData {
+    data: Vec<u8>,
+
+    /// Offset in data where each segment starts, together with its time.
+    times: Vec<(usize, NtlmTime)>,
+}
+
+impl TcpStreamData {
+    /// Orders the segments by sequence number, discarding retransmitted
+    /// data. In case of missing segments the data is just concatenated.
+    /// The stream starts in `start_seq` if the SYN was captured.
+    fn reassemble(segments: &mut [Segment], start_seq: Option<u32>) -> Self {
+        // otherwise, the stream starts in the earliest segment, taking into
+        // account that sequence numbers wrap around
+        let base_seq = match start_seq {
+            Some(seq) => seq,
+            None => {
+                let first_seq = segments[0].seq;
+                segments
+                    .iter()
+                    .map(|s| s.seq.wrapping_sub(first_seq) as i32)
+                    .min()
+                    .map(|d| first_seq.wrapping_add(d as u32))
+                    .unwrap_or(first_seq)
+            }
+        };
+        segments.sort_by_key(|s| s.seq.wrapping_sub(base_seq));
+
+        let mut data = Vec::new();
+        let mut times = Vec::new();
+        let mut next_seq = 0;
+
+        for segment in segments.iter() {
+            let rel_seq = segment.seq.wrapping_sub(base_seq) as usize;
+            let end_seq = rel_seq + segment.data.len();
+            if end_seq <= next_seq {
+                continue;
+            }
+
+            let start = next_seq.saturating_sub(rel_seq);
+            times.push((data.len(), segment.time));
+            data.extend(&segment.data[start..]);
+            next_seq = end_seq;
+        }
+
+        return Self { data, times };
+    }
+
+    fn time_at(&self, offset: usize) -> NtlmTime {
+        let i = match self.times.binary_search_by_key(&offset, |(o, _)| *o) {
+            Ok(i) => i,
+            Err(i) => i - 1,
+        };
+        return self.times[i].1;
+    }
+
+    /// Search NTLM challenges in raw NTLMSSP messages, which are used by
+    /// SMB, LDAP and others, and in HTTP authentication headers. Also
+    /// returns if the stream ends in the middle of a challenge.
+    fn find_challenges(
+        &self,
+    ) -> (Vec<(NtlmTime, Option<Protocol>, ChallengeMsg)>, bool) {
+        let mut challenges = Vec::new();
+        let mut incomplete = false;
+
+        let mut offset = 0;
+        while let Some(pos) = find(&self.data[offset..], NTLMSSP_SIGNATURE) {
+            let start = offset + pos;
+            match parse_ntlm_msg(&self.data[start..]) {
+                Ok(NtlmMsg::Challenge(ch)) => {
+                    challenges.push((self.time_at(start), None, ch))
+                }
+                Ok(_) => {}
+                Err(_) => incomplete = is_challenge_start(&self.data[start..]),
+            }
+            offset = start + NTLMSSP_SIGNATURE.len();
+        }
+
+        let mut offset = 0;
+        let mut lines = self.data.split(|b| *b == b'\n').peekable();
+        while let Some(line) = lines.next() {
+            if is_http_challenge_header(line) {
+                let text = String::from_utf8_lossy(line);
+                match decode_ntlm_msg(&text) {
+                    Ok(NtlmMsg::Challenge(ch)) => challenges.push((
+                        self.time_at(offset),
+                        Some(Protocol::Http),
+                        ch,
+                    )),
+                    Ok(_) => {}
+                    // the last line could continue in the next segment
+                    Err(_) => incomplete = lines.peek().is_none(),
+                }
+            }
+            offset += line.len() + 1;
+        }
+
+        return (challenges, incomplete);
+    }
+}
+
+/// Guess the protocol of raw NTLM messages by the server port.
+fn port_protocol(port: u16) -> Option<Protocol> {
+    match port {
+        139 | 445 => Some(Protocol::Smb),
+        _ => None,
+    }
+}
+
+/// Checks if the data could be the beginning of a challenge message.
+fn is_challenge_start(data: &[u8]) -> bool {
+    match data.get(8..12) {
+        Some(msg_type) => msg_type == NTLM_MSG_CHALLENGE.to_le_bytes(),
+        None => true,
+    }
+}
+
+/// Checks if the data contains any NTLM message or HTTP challenge header.
+fn contains_ntlm(data: &[u8]) -> bool {
+    return find(data, NTLMSSP_SIGNATURE).is_some()
+        || data.split(|b| *b == b'\n').any(is_http_challenge_header);
+}
+
+fn is_http_challenge_header(line: &[u8]) -> bool {
+    return HTTP_CHALLENGE_HEADERS.iter().any(|header| {
+        line.len() > header.len()
+            && line[..header.len()].eq_ignore_ascii_case(header.as_bytes())
+    });
+}
+
+fn find(data: &[u8], pattern: &[u8]) -> Option<usize> {
+    return data.windows(pattern.len()).position(|w| w == pattern);
+}
+
+fn slice_packet(linktype: Linktype, data: &[u8]) -> Option<SlicedPacket<'_>> {
+    match linktype {
+        Linktype::ETHERNET => SlicedPacket::from_ethernet(data).ok(),
+        Linktype::RAW | Linktype::IPV4 | Linktype::IPV6 => {
+            SlicedPacket::from_ip(data).ok()
+        }
+        // BSD loopback, with a 4 bytes header
+        Linktype::NULL | Linktype::LOOP => {
+            SlicedPacket::from_ip(data.get(4..)?).ok()
+        }
+        // Linux cooked capture, with a 16 bytes header that ends
+        // with the ether type
+        Linktype::LINUX_SLL => {
+            let ether_type =
+                u16::from_be_bytes([*data.get(14)?, *data.get(15)?]);
+            SlicedPacket::from_ether_type(ether_type, data.get(16..)?).ok()
+        }
+        _ => None,
+    }
+}
+
+fn new_time(secs: u32, frac: u32, resolution: u64) -> NtlmTime {
+    let nanos = (frac as u64 * 1_000_000_000 / resolution) as u32;
+    return Utc
+        .timestamp_opt(secs as i64, nanos)
+        .single()
+        .unwrap_or(ntlm::time::now());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use etherparse::PacketBuilder;
+    use ntlm::{AvPair, Version};
+    use std::io::Write;
+
+    const SERVER: ([u8; 4], u16) = ([10, 0, 0, 1], 445);
+    const CLIENT: ([u8; 4], u16) = ([10, 0, 0, 2], 50000);
+
+    fn challenge_msg() -> Vec<u8> {
+        let mut challenge = ChallengeMsg::default();
+        challenge.flags = ntlm::flags::NTLM_NEG_UNICODE
+            | ntlm::flags::NTLM_NEG_TARGET_INFO
+            | ntlm::flags::NTLM_NEG_VERSION;
+        challenge.version = Some(Version::windows7_7601());
+        challenge.target_info = vec![
+            AvPair::DnsComputerName("dc01.contoso.local".to_string()),
+            AvPair::EOL,
+        ]
+        .into();
+        return challenge.build();
+    }
+
+    /// Builds an ethernet TCP packet from the server to the client, with
+    /// the given flags ("S", "F" or "R").
+    fn server_packet(seq: u32, flags: &str, payload: &[u8]) -> Vec<u8> {
+        let mut tcp = PacketBuilder::ethernet2([1; 6], [2; 6])
+            .ipv4(SERVER.0, CLIENT.0, 64)
+            .tcp(SERVER.1, CLIENT.1, seq, 65535);
+        if flags.contains('S') {
+            tcp = tcp.syn().ack(1);
+        }
+        if flags.contains('F') {
+            tcp = tcp.fin();
+        }
+        if flags.contains('R') {
+            tcp = tcp.rst();
+        }
+
+        let mut packet = Vec::new();
+        tcp.write(&mut packet, payload).unwrap();
+        return packet;
+    }
+
+    /// Writes the packets in a pcap file and reads it.
+    fn read_packets(name: &str, packets: &[Vec<u8>]) -> TcpFlows {
+        let path = std::env::temp_dir().join(format!(
+            "ntlm-info-{}-{}.pcap",
+            name,
+            std::process::id()
+        ));
+
+        let mut data = Vec::new();
+        data.extend(&0xa1b2c3d4u32.to_le_bytes());
+        data.extend(&2u16.to_le_bytes());
+        data.extend(&4u16.to_le_bytes());
+        data.extend(&[0; 8]);
+        data.extend(&65535u32.to_le_bytes());
+        data.extend(&1u32.to_le_bytes());
+        for (i, packet) in packets.iter().enumerate() {
+            data.extend(&(1600000000 + i as u32).to_le_bytes());
+            data.extend(&0u32.to_le_bytes());
+            data.extend(&(packet.len() as u32).to_le_bytes());
+            data.extend(&(packet.len() as u32).to_le_bytes());
+            data.extend(packet);
+        }
+        File::create(&path).unwrap().write_all(&data).unwrap();
+
+        let mut flows = TcpFlows::new();
+        let result = flows.read_pcap(path.to_str().unwrap());
+        std::fs::remove_file(&path).unwrap();
+        result.unwrap();
+
+        return flows;
+    }
+
+    fn dns_computer_names(challenges: &[Challenge]) -> Vec<String> {
+        return challenges
+            .iter()
+            .filter_map(|ch| ch.challenge.dns_computer_name().cloned())
+            .collect();
+    }
+
+    #[test]
+    fn reassemble_out_of_order_segments() {
+        let mut payload = vec![0xfe, b'S', b'M', b'B'];
+        payload.extend(challenge_msg());
+        let (first, second) = payload.split_at(20);
+
+        let flows = read_packets(
+            "reorder",
+            &[
+                server_packet(1000, "S", &[]),
+                server_packet(1001 + first.len() as u32, "", second),
+                server_packet(1001, "", first),
+                server_packet(1001 + payload.len() as u32, "F", &[]),
+            ],
+        );
+        assert_eq!(flows.flows.len(), 0);
+
+        let challenges = flows.extract_challenges();
+        assert_eq!(dns_computer_names(&challenges), vec!["dc01.contoso.local"]);
+        assert_eq!(challenges[0].protocol, Some(Protocol::Smb));
+        assert_eq!(challenges[0].port(), Some(SERVER.1));
+    }
+
+    #[test]
+    fn separate_connections_with_same_port() {
+        let challenge = challenge_msg();
+        let mut junk = b"junk".to_vec();
+        junk.extend(&challenge[..30]);
+
+        // the first connection is reset in the middle of the challenge,
+        // and the second one reuses the port with other sequence numbers
+        let flows = read_packets(
+            "reuse",
+            &[
+                server_packet(5000, "S", &[]),
+                server_packet(5001, "", &junk),
+                server_packet(5001 + junk.len() as u32, "R", &[]),
+                server_packet(100, "S", &[]),
+                server_packet(101, "", &challenge[..30]),
+                server_packet(131, "", &challenge[30..]),
+            ],
+        );
+
+        let challenges = flows.extract_challenges();
+        assert_eq!(dns_computer_names(&challenges), vec!["dc01.contoso.local"]);
+    }
+
+    #[test]
+    fn release_flow_after_challenge() {
+        let header = format!(
+            "HTTP/1.1 401 Unauthorized\r\nWWW-Authenticate: NTLM {}\r\n\r\n",
+            base64::encode(challenge_msg())
+        );
+        let (first, second) = header.as_bytes().split_at(60);
+
+        let flows = read_packets(
+            "release",
+            &[
+                server_packet(1, "", first),
+                server_packet(1 + first.len() as u32, "", second),
+            ],
+        );
+
+        // the connection didn't end, but its data is not needed anymore
+        let flow = flows.flows.values().next().unwrap();
+        assert!(flow.done);
+        assert_eq!(flow.segments.len(), 0);
+
+        let challenges = flows.extract_challenges();
+        assert_eq!(dns_computer_names(&challenges), vec!["dc01.contoso.local"]);
+        assert_eq!(challenges[0].protocol, Some(Protocol::Http));
+    }
+
+    #[test]
+    fn limit_flow_size() {
+        let data = vec![0; MAX_FLOW_SIZE / 4];
+        let mut packets = vec![server_packet(0, "S", &[])];
+        for i in 0..5 {
+            packets.push(server_packet(1 + (i * data.len()) as u32, "", &data));
+        }
+        packets.push(server_packet(
+            1 + 5 * data.len() as u32,
+            "",
+            &challenge_msg(),
+        ));
+
+        let flows = read_packets("limit", &packets);
+        let flow = flows.flows.values().next().unwrap();
+        assert!(flow.done);
+        assert_eq!(flow.size, 0);
+
+        assert_eq!(flows.extract_challenges().len(), 0);
+    }
+}
@@ -0,0 +1,94 @@
+use log::error;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+
+/// Number of jobs that can wait in the queue per worker.
+const JOBS_PER_WORKER: usize = 2;
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// Number of jobs that are queued or running, to know when the pool is idle.
+type Pending = Arc<(Mutex<usize>, Condvar)>;
+
+/// Pool of workers that take the jobs from a bounded queue. When the queue
+/// is full, `execute` blocks until a worker is free, so the targets are
+/// generated as they are scanned and memory doesn't grow with the size of
+/// the ranges.
+pub struct BoundedPool {
+    sender: SyncSender<Job>,
+    workers: Vec<JoinHandle<()>>,
+    pending: Pending,
+}
+
+impl BoundedPool {
+    pub fn new(workers: usize) -> Self {
+        let (sender, receiver) = sync_channel(workers * JOBS_PER_WORKER);
+        let receiver = Arc::new(Mutex::new(receiver));
+        let pending: Pending = Arc::new((Mutex::new(0), Condvar::new()));
+
+        let workers = (0..workers)
+            .map(|_| {
+                let receiver = receiver.clone();
+                let pending = pending.clone();
+                thread::spawn(move || run_worker(receiver, pending))
+            })
+            .collect();
+
+        return Self {
+            sender,
+            workers,
+            pending,
+        };
+    }
+
+    /// Adds a job to the queue, waiting if it is full.
+    pub fn execute<F>(&self, job: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        *self.pending.0.lock().expect("Error locking pending jobs") += 1;
+        self.sender
+            .send(Box::new(job))
+            .expect("Error sending job to workers");
+    }
+
+    /// Waits until all the jobs are finished, keeping the workers to
+    /// execute more jobs.
+    pub fn wait(&self) {
+        let (count, finished) = &*self.pending;
+        let mut count = count.lock().expect("Error locking pending jobs");
+        while *count != 0 {
+            count = finished.wait(count).expect("Error locking pending jobs");
+        }
+    }
+
+    /// Waits until all the jobs are finished.
+    pub fn join(self) {
+        drop(self.sender);
+        for worker in self.workers {
+            worker.join().expect("Error joining worker thread");
+        }
+    }
+}
+
+fn run_worker(receiver: Arc<Mutex<Receiver<Job>>>, pending: Pending) {
+    loop {
+        let job = receiver.lock().expect("Error locking job queue").recv();
+        match job {
+            Ok(job) => {
+                // a panic in a job must not kill the worker, or the queue
+                // could get stuck
+                if panic::catch_unwind(AssertUnwindSafe(job)).is_err() {
+                    error!("Worker job panicked");
+                }
+
+                let (count, finished) = &*pending;
+                *count.lock().expect("Error locking pending jobs") -= 1;
+                finished.notify_all();
+            }
+            Err(_) => break,
+        }
+    }
+}
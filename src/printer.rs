@@ -1,41 +1,358 @@
-use crate::challenge::Challenge;
-use log::info;
-use ntlm::ChallengeMsg;
+mod bloodhound;
+mod csv;
+pub mod db;
+mod hosts;
+mod html;
+mod nmap;
+mod summary;
+mod template;
+
+use crate::args::OutputArgs;
+use crate::auth;
+use crate::challenge::{Challenge, ChallengeTarget, Failure};
+use crate::dns::DnsCheck;
+use chrono::Duration;
+use log::{error, info, warn};
+use ntlm::time::NtlmTime;
+use ntlm::{
+    AuthenticateMsg, AvPair, AvPairs, ChallengeMsg, LmResponse, NegotiateMsg,
+    NtlmResponse, Version,
+};
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
 use std::fs::File;
+use std::io::Write;
+use template::Template;
 
 pub struct Output {
     challenges: Vec<Challenge>,
+    failures: Vec<Failure>,
+
+    /// Negotiate and authenticate messages, sent by clients.
+    messages: Vec<JsonRecord>,
     out_file: Option<String>,
+    jsonl: Option<JsonLinesWriter>,
+    csv: Option<String>,
+    tsv: Option<String>,
+    bloodhound: Option<String>,
+    db: Option<String>,
+    hosts: Option<String>,
+    html: Option<String>,
+    nmap: Option<String>,
+    zone: Option<String>,
+    columns: Vec<String>,
+    format: Option<Template>,
+    full: bool,
+    summary: bool,
+
+    /// Time when the scan started.
+    scan_time: NtlmTime,
 }
 
 impl Output {
-    pub fn new(out_file: Option<String>) -> Self {
-        return Self {
-            challenges: Vec::new(),
-            out_file,
+    pub fn new(args: OutputArgs) -> Result<Self, String> {
+        let jsonl = match &args.jsonl {
+            Some(path) => Some(JsonLinesWriter::new(path)?),
+            None => None,
+        };
+
+        let columns = match args.columns {
+            Some(columns) => columns,
+            None => {
+                csv::DEFAULT_COLUMNS.iter().map(|c| c.to_string()).collect()
+            }
+        };
+        check_fields(&columns)?;
+
+        let format = match &args.format {
+            Some(format) => {
+                let template = Template::parse(format)?;
+                check_fields(&template.fields())?;
+                Some(template)
+            }
+            None => None,
         };
+
+        return Ok(Self {
+            challenges: Vec::new(),
+            failures: Vec::new(),
+            messages: Vec::new(),
+            out_file: args.json,
+            jsonl,
+            csv: args.csv,
+            tsv: args.tsv,
+            bloodhound: args.bloodhound,
+            db: args.db,
+            hosts: args.hosts,
+            html: args.html,
+            nmap: args.nmap,
+            zone: args.zone,
+            columns,
+            format,
+            full: args.full,
+            summary: args.summary,
+            scan_time: ntlm::time::now(),
+        });
     }
 }
 
 impl Output {
     pub fn add(&mut self, ch: Challenge) {
-        print_challenge(&ch);
-        self.challenges.push(ch);
+        match &self.format {
+            Some(template) => {
+                println!("{}", template.render(&challenge_fields(&ch)))
+            }
+            None => print_challenge(&ch, self.full),
+        }
+        if let Some(jsonl) = &mut self.jsonl {
+            jsonl.write(&JsonRecord::Challenge(JsonChallenge::from_challenge(
+                &ch,
+            )));
+        }
+
+        if self.keep_results() {
+            self.challenges.push(ch);
+        }
+    }
+
+    pub fn add_failure(&mut self, failure: Failure) {
+        warn!("{}", failure.error);
+        if let Some(jsonl) = &mut self.jsonl {
+            jsonl.write(&JsonRecord::Failure(JsonFailure::from_failure(
+                &failure,
+            )));
+        }
+
+        if self.keep_results() {
+            self.failures.push(failure);
+        }
+    }
+
+    /// Adds a negotiate message. Messages sent by clients are only written
+    /// to the outputs that are not about hosts: text, template, JSON, JSON
+    /// Lines and CSV/TSV.
+    pub fn add_negotiate(
+        &mut self,
+        target: &ChallengeTarget,
+        neg: &NegotiateMsg,
+    ) {
+        let record =
+            JsonRecord::Negotiate(JsonNegotiate::from_negotiate(target, neg));
+        match &self.format {
+            Some(template) => {
+                println!("{}", template.render(&record_fields(&record)))
+            }
+            None => print_negotiate(target, neg),
+        }
+        self.add_message(record);
+    }
+
+    /// Adds an authenticate message, written to the same outputs as the
+    /// negotiate messages.
+    pub fn add_authenticate(
+        &mut self,
+        target: &ChallengeTarget,
+        auth: &AuthenticateMsg,
+    ) {
+        let record = JsonRecord::Authenticate(
+            JsonAuthenticate::from_authenticate(target, auth),
+        );
+        match &self.format {
+            Some(template) => {
+                println!("{}", template.render(&record_fields(&record)))
+            }
+            None => print_authenticate(target, auth),
+        }
+        self.add_message(record);
+    }
+
+    fn add_message(&mut self, record: JsonRecord) {
+        if let Some(jsonl) = &mut self.jsonl {
+            jsonl.write(&record);
+        }
+
+        if self.keep_results() {
+            self.messages.push(record);
+        }
     }
 
-    pub fn finish(&self) -> Result<(), String> {
+    /// Indicates if results must be kept until the end, to be written
+    /// by the outputs that require all of them.
+    fn keep_results(&self) -> bool {
+        return self.out_file.is_some()
+            || self.csv.is_some()
+            || self.tsv.is_some()
+            || self.bloodhound.is_some()
+            || self.db.is_some()
+            || self.hosts.is_some()
+            || self.html.is_some()
+            || self.nmap.is_some()
+            || self.zone.is_some()
+            || self.summary;
+    }
+
+    /// Writes every output file, even if some of them fail, and returns
+    /// the errors of the failed ones.
+    pub fn finish(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+
         if let Some(out_file) = &self.out_file {
-            save_challenges(out_file, &self.challenges)?;
+            if let Err(err) = save_challenges(
+                out_file,
+                &self.challenges,
+                &self.failures,
+                &self.messages,
+            ) {
+                errors.push(err);
+            }
+        }
+
+        if self.csv.is_some() || self.tsv.is_some() {
+            let rows: Vec<Map<String, Value>> = self
+                .challenges
+                .iter()
+                .map(challenge_fields)
+                .chain(self.messages.iter().map(record_fields))
+                .collect();
+
+            if let Some(csv_file) = &self.csv {
+                if let Err(err) =
+                    csv::save_csv(csv_file, &rows, &self.columns, b',')
+                {
+                    errors.push(err);
+                }
+            }
+
+            if let Some(tsv_file) = &self.tsv {
+                if let Err(err) =
+                    csv::save_csv(tsv_file, &rows, &self.columns, b'\t')
+                {
+                    errors.push(err);
+                }
+            }
+        }
+
+        if let Some(bloodhound_dir) = &self.bloodhound {
+            if let Err(err) =
+                bloodhound::save_bloodhound(bloodhound_dir, &self.challenges)
+            {
+                errors.push(err);
+            }
+        }
+
+        if let Some(db_file) = &self.db {
+            if let Err(err) =
+                db::save_db(db_file, &self.challenges, &self.scan_time)
+            {
+                errors.push(err);
+            }
+        }
+
+        if let Some(html_file) = &self.html {
+            if let Err(err) = html::save_html(
+                html_file,
+                &self.challenges,
+                &self.failures,
+                &self.columns,
+            ) {
+                errors.push(err);
+            }
+        }
+
+        if let Some(nmap_file) = &self.nmap {
+            if let Err(err) =
+                nmap::save_nmap(nmap_file, &self.challenges, &self.scan_time)
+            {
+                errors.push(err);
+            }
+        }
+
+        if let Some(hosts_file) = &self.hosts {
+            if let Err(err) = hosts::save_hosts(hosts_file, &self.challenges) {
+                errors.push(err);
+            }
+        }
+
+        if let Some(zone_file) = &self.zone {
+            if let Err(err) = hosts::save_zone(zone_file, &self.challenges) {
+                errors.push(err);
+            }
+        }
+
+        if self.summary {
+            summary::print_summary(&self.challenges);
+        }
+
+        return errors;
+    }
+}
+
+/// Writer of JSON Lines, which writes each record as soon as it is
+/// received, so results are not lost if the program is interrupted.
+struct JsonLinesWriter {
+    path: String,
+    file: File,
+}
+
+impl JsonLinesWriter {
+    fn new(path: &str) -> Result<Self, String> {
+        let file = File::create(path)
+            .map_err(|e| format!("Error opening '{}': {}", path, e))?;
+
+        return Ok(Self {
+            path: path.to_string(),
+            file,
+        });
+    }
+
+    fn write(&mut self, record: &JsonRecord) {
+        let mut line =
+            serde_json::to_vec(record).expect("Error serializing JSON record");
+        line.push(b'\n');
+
+        if let Err(e) = self.file.write_all(&line).and(self.file.flush()) {
+            error!("Error writing JSON data in '{}': {}", self.path, e);
         }
+    }
+}
+
+/// Record of the JSON output, which can be the challenge retrieved from
+/// a target, the error produced when trying to retrieve it, or a message
+/// sent by a client.
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum JsonRecord {
+    Challenge(JsonChallenge),
+    Failure(JsonFailure),
+    Negotiate(JsonNegotiate),
+    Authenticate(JsonAuthenticate),
+}
 
-        return Ok(());
+#[derive(Serialize, Deserialize)]
+struct JsonFailure {
+    pub target: String,
+    pub error: String,
+    pub message: String,
+}
+
+impl JsonFailure {
+    fn from_failure(failure: &Failure) -> Self {
+        Self {
+            target: failure.target.clone(),
+            error: failure.error.category().to_string(),
+            message: failure.error.message().to_string(),
+        }
     }
 }
 
 #[derive(Serialize, Deserialize)]
 struct JsonChallenge {
     pub target: String,
+    pub ip: Option<String>,
+    pub hostname: Option<String>,
+    pub hostnames: Vec<String>,
+    pub port: Option<u16>,
+    pub protocol: Option<String>,
     pub nb_computer: Option<String>,
     pub nb_domain: Option<String>,
     pub dns_computer: Option<String>,
@@ -43,12 +360,29 @@ struct JsonChallenge {
     pub dns_tree: Option<String>,
     pub version: Option<String>,
     pub os_names: Option<Vec<String>>,
+    pub flags: u32,
+    pub flags_names: Vec<String>,
+    pub server_challenge: String,
+    pub target_name: Option<String>,
+    pub av_pairs: Vec<JsonAvPair>,
+    pub server_time: Option<String>,
+    pub skew: Option<f64>,
+    pub http_date: Option<String>,
+    pub http_skew: Option<f64>,
+    pub ptr: Option<Vec<String>>,
+    pub forward_ips: Option<Vec<String>>,
+    pub dns_check: Option<String>,
 }
 
 impl JsonChallenge {
     fn from_challenge(ch: &Challenge) -> Self {
         Self {
             target: ch.target.to_string(),
+            ip: ch.ip().map(|ip| ip.to_string()),
+            hostname: ch.hostname(),
+            hostnames: ch.hostnames(),
+            port: ch.port(),
+            protocol: ch.protocol.map(|p| p.to_string()),
             nb_computer: ch.challenge.nb_computer_name().map(|v| v.clone()),
             nb_domain: ch.challenge.nb_domain_name().map(|v| v.clone()),
             dns_computer: ch.challenge.dns_computer_name().map(|v| v.clone()),
@@ -64,19 +398,238 @@ impl JsonChallenge {
                 .version
                 .as_ref()
                 .map(|v| v.os_names().iter().map(|s| s.to_string()).collect()),
+            flags: ch.challenge.flags,
+            flags_names: flags_names(ch.challenge.flags),
+            server_challenge: hex::encode(ch.challenge.server_challenge),
+            target_name: ch.challenge.target_name.clone(),
+            av_pairs: ch
+                .challenge
+                .target_info
+                .iter()
+                .map(JsonAvPair::from_av_pair)
+                .collect(),
+            server_time: ch.server_time().map(|t| t.to_rfc3339()),
+            skew: ch.skew().map(|d| skew_secs(&d)),
+            http_date: ch.http_date.map(|t| t.to_rfc3339()),
+            http_skew: ch.http_skew().map(|d| skew_secs(&d)),
+            ptr: ch.dns.as_ref().map(|d| d.ptr.clone()),
+            forward_ips: ch
+                .dns
+                .as_ref()
+                .map(|d| d.forward.iter().map(|ip| ip.to_string()).collect()),
+            dns_check: ch.dns.as_ref().map(|d| d.status.to_string()),
         }
     }
 }
 
-fn save_challenges(out_file: &str, chs: &Vec<Challenge>) -> Result<(), String> {
+#[derive(Serialize, Deserialize)]
+struct JsonNegotiate {
+    pub target: String,
+    pub message: String,
+    pub flags: u32,
+    pub flags_names: Vec<String>,
+    pub domain: Option<String>,
+    pub workstation: Option<String>,
+    pub version: Option<String>,
+}
+
+impl JsonNegotiate {
+    fn from_negotiate(target: &ChallengeTarget, neg: &NegotiateMsg) -> Self {
+        Self {
+            target: target.to_string(),
+            message: "negotiate".to_string(),
+            flags: neg.flags,
+            flags_names: flags_names(neg.flags),
+            domain: neg.domain.clone(),
+            workstation: neg.workstation.clone(),
+            version: neg.version.as_ref().map(version_to_string),
+        }
+    }
+}
+
+/// Authenticate message, where the responses are encoded in hex as they
+/// are sent, to be used by cracking tools.
+#[derive(Serialize, Deserialize)]
+struct JsonAuthenticate {
+    pub target: String,
+    pub message: String,
+    pub flags: u32,
+    pub flags_names: Vec<String>,
+    pub domain: String,
+    pub user: String,
+    pub workstation: String,
+    pub version: Option<String>,
+    pub mic: Option<String>,
+    pub ntlm_version: Option<String>,
+    pub lm_response: Option<String>,
+    pub nt_response: Option<String>,
+    pub client_timestamp: Option<String>,
+    pub av_pairs: Vec<JsonAvPair>,
+    pub session_key: Option<String>,
+}
+
+impl JsonAuthenticate {
+    fn from_authenticate(
+        target: &ChallengeTarget,
+        auth: &AuthenticateMsg,
+    ) -> Self {
+        let client_challenge = match &auth.ntlm_response {
+            Some(NtlmResponse::V2(nt)) => Some(&nt.client_challenge),
+            _ => None,
+        };
+
+        Self {
+            target: target.to_string(),
+            message: "authenticate".to_string(),
+            flags: auth.flags,
+            flags_names: flags_names(auth.flags),
+            domain: auth.domain_name.clone(),
+            user: auth.username.clone(),
+            workstation: auth.workstation.clone(),
+            version: auth.version.as_ref().map(version_to_string),
+            mic: auth.mic.map(hex::encode),
+            ntlm_version: auth.ntlm_response.as_ref().map(|nt| match nt {
+                NtlmResponse::V1(_) => "v1".to_string(),
+                NtlmResponse::V2(_) => "v2".to_string(),
+            }),
+            lm_response: auth
+                .lm_response
+                .as_ref()
+                .map(|lm| hex::encode(lm.build())),
+            nt_response: auth
+                .ntlm_response
+                .as_ref()
+                .map(|nt| hex::encode(nt.build())),
+            client_timestamp: client_challenge
+                .map(|c| c.timestamp.to_rfc3339()),
+            av_pairs: client_challenge
+                .map(|c| {
+                    c.av_pairs.iter().map(JsonAvPair::from_av_pair).collect()
+                })
+                .unwrap_or_default(),
+            session_key: auth.encrypted_session_key.as_ref().map(hex::encode),
+        }
+    }
+}
+
+fn flags_names(flags: u32) -> Vec<String> {
+    return auth::neg_flags_names(flags)
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+}
+
+#[derive(Serialize, Deserialize)]
+struct JsonAvPair {
+    pub id: u16,
+    pub name: String,
+    pub value: String,
+}
+
+impl JsonAvPair {
+    fn from_av_pair(av: &AvPair) -> Self {
+        Self {
+            id: av_pair_id(av),
+            name: av_pair_name(av),
+            value: av_pair_value(av),
+        }
+    }
+}
+
+/// Returns the fields of a challenge, by name, as they are in the JSON
+/// output, plus `os` that includes the OS names in one string.
+fn challenge_fields(ch: &Challenge) -> Map<String, Value> {
+    let mut fields =
+        match serde_json::to_value(JsonChallenge::from_challenge(ch)) {
+            Ok(Value::Object(fields)) => fields,
+            _ => unreachable!("JSON challenge must be an object"),
+        };
+
+    let os = field_to_string(fields.get("os_names").unwrap_or(&Value::Null));
+    fields.insert("os".to_string(), Value::String(os));
+
+    return fields;
+}
+
+/// Returns the fields of a record, by name, as they are in the JSON output.
+fn record_fields(record: &JsonRecord) -> Map<String, Value> {
+    match serde_json::to_value(record) {
+        Ok(Value::Object(fields)) => fields,
+        _ => unreachable!("JSON record must be an object"),
+    }
+}
+
+/// Checks that all the given names are fields of the challenges or the
+/// messages sent by clients.
+fn check_fields(names: &[String]) -> Result<(), String> {
+    let target = ChallengeTarget::Input(0);
+    let dummy =
+        Challenge::new(ChallengeTarget::Input(0), ChallengeMsg::default());
+    let mut fields = challenge_fields(&dummy);
+    fields.extend(record_fields(&JsonRecord::Negotiate(
+        JsonNegotiate::from_negotiate(&target, &NegotiateMsg::default()),
+    )));
+    fields.extend(record_fields(&JsonRecord::Authenticate(
+        JsonAuthenticate::from_authenticate(
+            &target,
+            &AuthenticateMsg::default(),
+        ),
+    )));
+
+    for name in names {
+        if !fields.contains_key(name) {
+            let mut valid: Vec<&String> = fields.keys().collect();
+            valid.sort();
+            return Err(format!(
+                "Unknown field '{}', valid fields are: {}",
+                name,
+                valid
+                    .iter()
+                    .map(|s| s.as_str())
+                    .collect::<Vec<&str>>()
+                    .join(", ")
+            ));
+        }
+    }
+
+    return Ok(());
+}
+
+/// Converts the value of a field to plain text. Lists are joined with " | ".
+fn field_to_string(value: &Value) -> String {
+    match value {
+        Value::Null => "".to_string(),
+        Value::String(s) => s.clone(),
+        Value::Array(values) => values
+            .iter()
+            .map(field_to_string)
+            .collect::<Vec<String>>()
+            .join(" | "),
+        _ => value.to_string(),
+    }
+}
+
+fn save_challenges(
+    out_file: &str,
+    chs: &Vec<Challenge>,
+    failures: &Vec<Failure>,
+    messages: &[JsonRecord],
+) -> Result<(), String> {
     let mut json_chs = Vec::new();
     for ch in chs {
-        json_chs.push(JsonChallenge::from_challenge(&ch));
+        json_chs
+            .push(JsonRecord::Challenge(JsonChallenge::from_challenge(&ch)));
+    }
+
+    for failure in failures {
+        json_chs.push(JsonRecord::Failure(JsonFailure::from_failure(failure)));
     }
 
     let file = File::create(out_file)
         .map_err(|e| format!("Error opening '{}': {}", out_file, e))?;
-    serde_json::to_writer(&file, &json_chs).map_err(|e| {
+
+    let records: Vec<&JsonRecord> = json_chs.iter().chain(messages).collect();
+    serde_json::to_writer(&file, &records).map_err(|e| {
         format!("Error writing JSON data in '{}': {}", out_file, e)
     })?;
 
@@ -85,12 +638,23 @@ fn save_challenges(out_file: &str, chs: &Vec<Challenge>) -> Result<(), String> {
     return Ok(());
 }
 
-pub fn print_challenge(ch: &Challenge) {
-    println!(
-        "\nTarget: {}\n{}",
-        ch.target,
-        ntlm_challenge_to_string(&ch.challenge)
-    );
+pub fn print_challenge(ch: &Challenge, full: bool) {
+    let mut msg = ntlm_challenge_to_string(&ch.challenge);
+
+    let time = time_to_string(ch);
+    if time.len() > 0 {
+        msg = format!("{}\n{}", msg, time);
+    }
+
+    if let Some(dns) = &ch.dns {
+        msg = format!("{}\n{}", msg, dns_check_to_string(dns));
+    }
+
+    if full {
+        msg = format!("{}\n{}", msg, ntlm_challenge_details(&ch.challenge));
+    }
+
+    println!("\nTarget: {}\n{}", ch.target, msg);
 }
 
 fn ntlm_challenge_to_string(nt_ch: &ChallengeMsg) -> String {
@@ -129,3 +693,319 @@ fn ntlm_challenge_to_string(nt_ch: &ChallengeMsg) -> String {
 
     return msg.join("\n");
 }
+
+fn print_negotiate(target: &ChallengeTarget, neg: &NegotiateMsg) {
+    let mut msg = vec!["Message: Negotiate".to_string()];
+    msg.push(flags_to_string(neg.flags));
+
+    if let Some(domain) = &neg.domain {
+        msg.push(format!("Domain: {}", domain));
+    }
+
+    if let Some(workstation) = &neg.workstation {
+        msg.push(format!("Workstation: {}", workstation));
+    }
+
+    if let Some(version) = &neg.version {
+        msg.push(format!("Version: {}", version_to_string(version)));
+    }
+
+    println!("\nTarget: {}\n{}", target, msg.join("\n"));
+}
+
+fn print_authenticate(target: &ChallengeTarget, auth: &AuthenticateMsg) {
+    let mut msg = vec!["Message: Authenticate".to_string()];
+    msg.push(flags_to_string(auth.flags));
+    msg.push(format!("Domain: {}", auth.domain_name));
+    msg.push(format!("User: {}", auth.username));
+    msg.push(format!("Workstation: {}", auth.workstation));
+
+    if let Some(version) = &auth.version {
+        msg.push(format!("Version: {}", version_to_string(version)));
+    }
+
+    if let Some(mic) = &auth.mic {
+        msg.push(format!("Mic: {}", hex::encode(mic)));
+    }
+
+    match &auth.lm_response {
+        Some(LmResponse::V1(lm)) => {
+            msg.push(format!("LmResponse: {}", hex::encode(lm.response)));
+        }
+        Some(LmResponse::V2(lm)) => {
+            msg.push(format!("Lmv2Response: {}", hex::encode(lm.response)));
+            msg.push(format!(
+                "Lmv2ClientChallenge: {}",
+                hex::encode(lm.client_challenge)
+            ));
+        }
+        None => {}
+    }
+
+    match &auth.ntlm_response {
+        Some(NtlmResponse::V1(nt)) => {
+            msg.push(format!("NtlmResponse: {}", hex::encode(nt.response)));
+        }
+        Some(NtlmResponse::V2(nt)) => {
+            let client_ch = &nt.client_challenge;
+            msg.push(format!("Ntlmv2Response: {}", hex::encode(nt.response)));
+            msg.push(format!(
+                "ClientChallenge: {}",
+                hex::encode(client_ch.client_challenge)
+            ));
+            msg.push(format!(
+                "ClientTimestamp: {}",
+                client_ch.timestamp.to_rfc3339()
+            ));
+            msg.push(av_pairs_to_string(&client_ch.av_pairs));
+        }
+        None => {}
+    }
+
+    if let Some(key) = &auth.encrypted_session_key {
+        msg.push(format!("EncryptedSessionKey: {}", hex::encode(key)));
+    }
+
+    println!("\nTarget: {}\n{}", target, msg.join("\n"));
+}
+
+/// Kerberos rejects tickets when the clock skew with the KDC is bigger
+/// than 5 minutes by default.
+const KERBEROS_MAX_SKEW_SECS: i64 = 5 * 60;
+
+fn time_to_string(ch: &Challenge) -> String {
+    let mut msg = Vec::new();
+    if let Some(time) = ch.server_time() {
+        msg.push(format!("Time: {}", time.to_rfc3339()));
+    }
+
+    if let Some(skew) = ch.skew() {
+        msg.push(format!("Skew: {}", skew_to_string(&skew)));
+    }
+
+    if let Some(date) = ch.http_date {
+        msg.push(format!("HttpDate: {}", date.to_rfc3339()));
+    }
+
+    if let Some(skew) = ch.http_skew() {
+        msg.push(format!("HttpSkew: {}", skew_to_string(&skew)));
+    }
+
+    return msg.join("\n");
+}
+
+fn dns_check_to_string(dns: &DnsCheck) -> String {
+    let mut msg = Vec::new();
+    if dns.ptr.len() != 0 {
+        msg.push(format!("Ptr: {}", dns.ptr.join(" | ")));
+    }
+
+    if dns.forward.len() != 0 {
+        let forward: Vec<String> =
+            dns.forward.iter().map(|ip| ip.to_string()).collect();
+        msg.push(format!("Forward: {}", forward.join(" | ")));
+    }
+
+    msg.push(format!("DnsCheck: {}", dns.status));
+
+    return msg.join("\n");
+}
+
+fn skew_to_string(skew: &Duration) -> String {
+    let mut msg = format!("{:+.3}s", skew_secs(skew));
+    if skew.num_seconds().abs() > KERBEROS_MAX_SKEW_SECS {
+        msg.push_str(" (exceeds Kerberos 5 minutes limit)");
+    }
+    return msg;
+}
+
+fn skew_secs(skew: &Duration) -> f64 {
+    return skew.num_milliseconds() as f64 / 1000.0;
+}
+
+/// Returns the fields of the challenge that are not shown by default:
+/// negotiate flags, server challenge, target name and all the AV pairs.
+fn ntlm_challenge_details(nt_ch: &ChallengeMsg) -> String {
+    let mut msg = Vec::new();
+    msg.push(flags_to_string(nt_ch.flags));
+
+    msg.push(format!(
+        "ServerChallenge: {}",
+        hex::encode(nt_ch.server_challenge)
+    ));
+
+    if let Some(target_name) = &nt_ch.target_name {
+        msg.push(format!("TargetName: {}", target_name));
+    }
+
+    msg.push(av_pairs_to_string(&nt_ch.target_info));
+
+    return msg.join("\n");
+}
+
+fn flags_to_string(flags: u32) -> String {
+    let mut msg = vec![format!("Flags: 0x{:08x}", flags)];
+    for name in auth::neg_flags_names(flags) {
+        msg.push(format!("  {}", name));
+    }
+
+    return msg.join("\n");
+}
+
+fn av_pairs_to_string(av_pairs: &AvPairs) -> String {
+    let mut msg = vec!["AvPairs:".to_string()];
+    for av in av_pairs.iter() {
+        msg.push(format!("  {}: {}", av_pair_name(av), av_pair_value(av)));
+    }
+
+    return msg.join("\n");
+}
+
+fn version_to_string(version: &Version) -> String {
+    return format!("{}.{}.{}", version.major, version.minor, version.build);
+}
+
+/// Returns the AV pair ID, as defined in [MS-NLMP] section 2.2.2.1.
+fn av_pair_id(av: &AvPair) -> u16 {
+    match av {
+        AvPair::EOL => 0x0000,
+        AvPair::NbComputerName(_) => 0x0001,
+        AvPair::NbDomainName(_) => 0x0002,
+        AvPair::DnsComputerName(_) => 0x0003,
+        AvPair::DnsDomainName(_) => 0x0004,
+        AvPair::DnsTreeName(_) => 0x0005,
+        AvPair::Flags(_) => 0x0006,
+        AvPair::Timestamp(_) => 0x0007,
+        AvPair::SingleHost(_) => 0x0008,
+        AvPair::TargetName(_) => 0x0009,
+        AvPair::ChannelBindings(_) => 0x000a,
+        AvPair::Raw(id, _) => *id,
+    }
+}
+
+fn av_pair_name(av: &AvPair) -> String {
+    let name = match av {
+        AvPair::EOL => "MsvAvEOL",
+        AvPair::NbComputerName(_) => "MsvAvNbComputerName",
+        AvPair::NbDomainName(_) => "MsvAvNbDomainName",
+        AvPair::DnsComputerName(_) => "MsvAvDnsComputerName",
+        AvPair::DnsDomainName(_) => "MsvAvDnsDomainName",
+        AvPair::DnsTreeName(_) => "MsvAvDnsTreeName",
+        AvPair::Flags(_) => "MsvAvFlags",
+        AvPair::Timestamp(_) => "MsvAvTimestamp",
+        AvPair::SingleHost(_) => "MsvAvSingleHost",
+        AvPair::TargetName(_) => "MsvAvTargetName",
+        AvPair::ChannelBindings(_) => "MsvAvChannelBindings",
+        AvPair::Raw(id, _) => return format!("Unknown(0x{:04x})", id),
+    };
+
+    return name.to_string();
+}
+
+fn av_pair_value(av: &AvPair) -> String {
+    match av {
+        AvPair::EOL => "".to_string(),
+        AvPair::NbComputerName(s)
+        | AvPair::NbDomainName(s)
+        | AvPair::DnsComputerName(s)
+        | AvPair::DnsDomainName(s)
+        | AvPair::DnsTreeName(s)
+        | AvPair::TargetName(s) => s.clone(),
+        AvPair::Flags(flags) => {
+            let names = auth::av_flags_names(*flags);
+            if names.len() == 0 {
+                format!("0x{:08x}", flags)
+            } else {
+                format!("0x{:08x} ({})", flags, names.join(" | "))
+            }
+        }
+        AvPair::Timestamp(t) => t.to_rfc3339(),
+        AvPair::SingleHost(sh) => format!(
+            "data={} machine_id={}",
+            hex::encode(sh.data),
+            hex::encode(sh.machine_id)
+        ),
+        AvPair::ChannelBindings(cb) => hex::encode(cb),
+        AvPair::Raw(_, value) => hex::encode(value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Challenge of the example of [MS-NLMP] section 4.2.4.3, with an
+    /// unknown AV pair (0x00ff) added before MsvAvEOL.
+    const CHALLENGE: &'static str = "4e544c4d53535000020000000c000c00380000\
+        0033828ae20123456789abcdef00000000000000002a002a004400000006007017\
+        0000000f53006500720076006500720002000c0044006f006d00610069006e0001\
+        000c00530065007200760065007200ff000200dead00000000";
+
+    fn challenge_msg() -> ChallengeMsg {
+        let raw = hex::decode(CHALLENGE).unwrap();
+        return ChallengeMsg::parse(&raw).unwrap();
+    }
+
+    #[test]
+    fn decode_flags_by_name() {
+        let nt_ch = challenge_msg();
+        assert_eq!(nt_ch.flags, 0xe28a8233);
+        assert_eq!(
+            auth::neg_flags_names(nt_ch.flags),
+            vec![
+                "NTLMSSP_NEGOTIATE_UNICODE",
+                "NTLMSSP_NEGOTIATE_OEM",
+                "NTLMSSP_NEGOTIATE_SIGN",
+                "NTLMSSP_NEGOTIATE_SEAL",
+                "NTLMSSP_NEGOTIATE_NTLM",
+                "NTLMSSP_NEGOTIATE_ALWAYS_SIGN",
+                "NTLMSSP_TARGET_TYPE_SERVER",
+                "NTLMSSP_NEGOTIATE_EXTENDED_SESSIONSECURITY",
+                "NTLMSSP_NEGOTIATE_TARGET_INFO",
+                "NTLMSSP_NEGOTIATE_VERSION",
+                "NTLMSSP_NEGOTIATE_128",
+                "NTLMSSP_NEGOTIATE_KEY_EXCH",
+                "NTLMSSP_NEGOTIATE_56",
+            ]
+        );
+    }
+
+    #[test]
+    fn decode_challenge_details() {
+        let details = ntlm_challenge_details(&challenge_msg());
+        assert!(details.starts_with("Flags: 0xe28a8233\n"));
+        assert!(details.contains("\nServerChallenge: 0123456789abcdef\n"));
+        assert!(details.contains("\n  MsvAvNbDomainName: Domain\n"));
+        assert!(details.contains("\n  MsvAvNbComputerName: Server\n"));
+        assert!(details.ends_with("\n  Unknown(0x00ff): dead"));
+    }
+
+    #[test]
+    fn decode_unknown_av_pair_as_hex() {
+        let nt_ch = challenge_msg();
+        let av = nt_ch
+            .target_info
+            .iter()
+            .find(|av| av_pair_id(av) == 0x00ff)
+            .unwrap();
+
+        let json = JsonAvPair::from_av_pair(av);
+        assert_eq!(json.id, 0x00ff);
+        assert_eq!(json.name, "Unknown(0x00ff)");
+        assert_eq!(json.value, "dead");
+    }
+
+    #[test]
+    fn flag_skew_beyond_kerberos_limit() {
+        assert_eq!(skew_to_string(&Duration::milliseconds(2500)), "+2.500s");
+        assert_eq!(skew_to_string(&Duration::minutes(-5)), "-300.000s");
+        assert_eq!(
+            skew_to_string(&Duration::seconds(301)),
+            "+301.000s (exceeds Kerberos 5 minutes limit)"
+        );
+        assert_eq!(
+            skew_to_string(&Duration::minutes(-10)),
+            "-600.000s (exceeds Kerberos 5 minutes limit)"
+        );
+    }
+}
@@ -0,0 +1,272 @@
+use crate::challenge::Challenge;
+use log::info;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::path::Path;
+
+/// Version of the BloodHound JSON format, as produced by SharpHound 3.
+const BLOODHOUND_VERSION: u32 = 3;
+
+/// Save the computers and domains in BloodHound JSON format, in the files
+/// computers.json and domains.json of the given directory. Since the SIDs
+/// are unknown, the objects are identified by their names in uppercase.
+pub fn save_bloodhound(
+    out_dir: &str,
+    chs: &Vec<Challenge>,
+) -> Result<(), String> {
+    let mut computers: BTreeMap<String, Value> = BTreeMap::new();
+    // computers of each domain
+    let mut domains: BTreeMap<String, Vec<String>> = BTreeMap::new();
+
+    for ch in chs {
+        let domain = match challenge_domain(ch) {
+            Some(domain) => domain,
+            None => continue,
+        };
+
+        let name = match ch.challenge.dns_computer_name() {
+            Some(name) if name.len() != 0 => name.to_uppercase(),
+            _ => match ch.challenge.nb_computer_name() {
+                Some(name) if name.len() != 0 => {
+                    format!("{}.{}", name.to_uppercase(), domain)
+                }
+                _ => continue,
+            },
+        };
+
+        let os = ch
+            .challenge
+            .version
+            .as_ref()
+            .map(|v| v.os_names().join(" | "));
+
+        if computers.contains_key(&name) {
+            continue;
+        }
+        computers.insert(name.clone(), new_computer(&name, &domain, os));
+        domains.entry(domain).or_default().push(name);
+
+        if let Some(tree) = ch.challenge.dns_tree_name() {
+            if tree.len() != 0 {
+                domains.entry(tree.to_uppercase()).or_default();
+            }
+        }
+    }
+
+    fs::create_dir_all(out_dir)
+        .map_err(|e| format!("Error creating '{}': {}", out_dir, e))?;
+
+    save_objects(out_dir, "computers", computers.into_values().collect())?;
+    save_objects(
+        out_dir,
+        "domains",
+        domains
+            .iter()
+            .map(|(name, computers)| new_domain(name, computers))
+            .collect(),
+    )?;
+
+    return Ok(());
+}
+
+/// Returns the domain of the host in uppercase, which is the DNS domain or
+/// the NetBIOS domain if unknown. Hosts whose domain is its own computer
+/// name are not in a domain.
+fn challenge_domain(ch: &Challenge) -> Option<String> {
+    let nb_domain = ch.challenge.nb_domain_name().filter(|d| d.len() != 0)?;
+    if let Some(nb_computer) = ch.challenge.nb_computer_name() {
+        if nb_domain.eq_ignore_ascii_case(nb_computer) {
+            return None;
+        }
+    }
+
+    match ch.challenge.dns_domain_name() {
+        Some(dns_domain) if dns_domain.len() != 0 => {
+            Some(dns_domain.to_uppercase())
+        }
+        _ => Some(nb_domain.to_uppercase()),
+    }
+}
+
+fn new_computer(name: &str, domain: &str, os: Option<String>) -> Value {
+    return json!({
+        "ObjectIdentifier": name,
+        "Properties": {
+            "name": name,
+            "domain": domain,
+            "objectid": name,
+            "operatingsystem": os,
+            "enabled": true,
+            "highvalue": false,
+            "description": "Discovered by ntlm-info",
+        },
+        "AllowedToDelegate": [],
+        "AllowedToAct": [],
+        "PrimaryGroupSid": null,
+        "Sessions": [],
+        "LocalAdmins": [],
+        "RemoteDesktopUsers": [],
+        "DcomUsers": [],
+        "PSRemoteUsers": [],
+        "Aces": [],
+    });
+}
+
+fn new_domain(name: &str, computers: &Vec<String>) -> Value {
+    let dn: Vec<String> =
+        name.split('.').map(|dc| format!("DC={}", dc)).collect();
+
+    return json!({
+        "ObjectIdentifier": name,
+        "Properties": {
+            "name": name,
+            "domain": name,
+            "objectid": name,
+            "distinguishedname": dn.join(","),
+            "highvalue": true,
+            "description": "Discovered by ntlm-info",
+        },
+        "Users": [],
+        "Computers": computers,
+        "ChildOus": [],
+        "Trusts": [],
+        "Links": [],
+        "RemoteDesktopUsers": [],
+        "LocalAdmins": [],
+        "DcomUsers": [],
+        "PSRemoteUsers": [],
+        "Aces": [],
+    });
+}
+
+fn save_objects(
+    out_dir: &str,
+    object_type: &str,
+    objects: Vec<Value>,
+) -> Result<(), String> {
+    let path = Path::new(out_dir).join(format!("{}.json", object_type));
+    let path = path.to_string_lossy();
+
+    let data = json!({
+        "meta": {
+            "count": objects.len(),
+            "type": object_type,
+            "version": BLOODHOUND_VERSION,
+        },
+        object_type: objects,
+    });
+
+    let file = File::create(path.as_ref())
+        .map_err(|e| format!("Error opening '{}': {}", path, e))?;
+    serde_json::to_writer(&file, &data)
+        .map_err(|e| format!("Error writing JSON data in '{}': {}", path, e))?;
+
+    info!("Save {} in '{}'", object_type, path);
+
+    return Ok(());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::challenge::ChallengeTarget;
+    use ntlm::{AvPair, ChallengeMsg, Version};
+
+    fn challenge(av_pairs: Vec<AvPair>, version: Option<Version>) -> Challenge {
+        let mut msg = ChallengeMsg::default();
+        msg.target_info = av_pairs.into();
+        msg.version = version;
+        return Challenge::new(ChallengeTarget::Url("http://ws/".into()), msg);
+    }
+
+    fn ws01() -> Challenge {
+        return challenge(
+            vec![
+                AvPair::NbDomainName("CONTOSO".to_string()),
+                AvPair::NbComputerName("WS01".to_string()),
+                AvPair::DnsDomainName("contoso.local".to_string()),
+                AvPair::DnsComputerName("ws01.contoso.local".to_string()),
+                AvPair::DnsTreeName("contoso.local".to_string()),
+                AvPair::EOL,
+            ],
+            Some(Version::windows7_7601()),
+        );
+    }
+
+    fn read_objects(out_dir: &Path, object_type: &str) -> Value {
+        let path = out_dir.join(format!("{}.json", object_type));
+        let data = fs::read_to_string(&path).unwrap();
+        return serde_json::from_str(&data).unwrap();
+    }
+
+    #[test]
+    fn save_computers_and_domains() {
+        let chs = vec![
+            ws01(),
+            ws01(),
+            // without DNS computer name, in a child domain
+            challenge(
+                vec![
+                    AvPair::NbDomainName("SUB".to_string()),
+                    AvPair::NbComputerName("SRV02".to_string()),
+                    AvPair::DnsDomainName("sub.contoso.local".to_string()),
+                    AvPair::DnsTreeName("contoso.local".to_string()),
+                    AvPair::EOL,
+                ],
+                None,
+            ),
+            // workgroup host
+            challenge(
+                vec![
+                    AvPair::NbDomainName("WS03".to_string()),
+                    AvPair::NbComputerName("WS03".to_string()),
+                    AvPair::EOL,
+                ],
+                None,
+            ),
+        ];
+
+        let out_dir = std::env::temp_dir()
+            .join(format!("ntlm-info-bloodhound-{}", std::process::id()));
+        let result = save_bloodhound(out_dir.to_str().unwrap(), &chs);
+        let computers = read_objects(&out_dir, "computers");
+        let domains = read_objects(&out_dir, "domains");
+        fs::remove_dir_all(&out_dir).unwrap();
+        result.unwrap();
+
+        assert_eq!(
+            computers["meta"],
+            json!({"count": 2, "type": "computers", "version": 3})
+        );
+        let srv02 = &computers["computers"][0];
+        assert_eq!(srv02["ObjectIdentifier"], "SRV02.SUB.CONTOSO.LOCAL");
+        assert_eq!(srv02["Properties"]["name"], "SRV02.SUB.CONTOSO.LOCAL");
+        assert_eq!(srv02["Properties"]["domain"], "SUB.CONTOSO.LOCAL");
+        assert_eq!(srv02["Properties"]["operatingsystem"], Value::Null);
+        let ws01 = &computers["computers"][1];
+        assert_eq!(ws01["ObjectIdentifier"], "WS01.CONTOSO.LOCAL");
+        assert_eq!(ws01["Properties"]["domain"], "CONTOSO.LOCAL");
+        assert_eq!(
+            ws01["Properties"]["operatingsystem"],
+            Version::windows7_7601().os_names().join(" | ")
+        );
+        assert_eq!(ws01["Sessions"], json!([]));
+
+        assert_eq!(
+            domains["meta"],
+            json!({"count": 2, "type": "domains", "version": 3})
+        );
+        let contoso = &domains["domains"][0];
+        assert_eq!(contoso["ObjectIdentifier"], "CONTOSO.LOCAL");
+        assert_eq!(
+            contoso["Properties"]["distinguishedname"],
+            "DC=CONTOSO,DC=LOCAL"
+        );
+        assert_eq!(contoso["Computers"], json!(["WS01.CONTOSO.LOCAL"]));
+        assert_eq!(contoso["Trusts"], json!([]));
+        let sub = &domains["domains"][1];
+        assert_eq!(sub["ObjectIdentifier"], "SUB.CONTOSO.LOCAL");
+        assert_eq!(sub["Computers"], json!(["SRV02.SUB.CONTOSO.LOCAL"]));
+    }
+}
@@ -0,0 +1,54 @@
+use super::field_to_string;
+use log::info;
+use serde_json::{Map, Value};
+
+pub const DEFAULT_COLUMNS: &'static [&'static str] = &[
+    "target",
+    "ip",
+    "hostname",
+    "hostnames",
+    "port",
+    "protocol",
+    "nb_computer",
+    "nb_domain",
+    "dns_computer",
+    "dns_domain",
+    "dns_tree",
+    "version",
+    "os",
+];
+
+/// Save the fields of the challenges and messages in CSV format, with the
+/// given columns. Missing fields are left empty.
+pub fn save_csv(
+    out_file: &str,
+    rows: &[Map<String, Value>],
+    columns: &[String],
+    delimiter: u8,
+) -> Result<(), String> {
+    let mut writer = ::csv::WriterBuilder::new()
+        .delimiter(delimiter)
+        .from_path(out_file)
+        .map_err(|e| format!("Error opening '{}': {}", out_file, e))?;
+
+    let write_err = |e: ::csv::Error| {
+        format!("Error writing CSV data in '{}': {}", out_file, e)
+    };
+
+    writer.write_record(columns).map_err(write_err)?;
+
+    for fields in rows {
+        let row = columns
+            .iter()
+            .map(|c| field_to_string(fields.get(c).unwrap_or(&Value::Null)));
+        writer.write_record(row).map_err(write_err)?;
+    }
+
+    writer.flush().map_err(|e| {
+        format!("Error writing CSV data in '{}': {}", out_file, e)
+    })?;
+
+    info!("Save challenges in '{}'", out_file);
+
+    return Ok(());
+}
@@ -0,0 +1,249 @@
+use super::JsonChallenge;
+use crate::challenge::Challenge;
+use log::info;
+use ntlm::time::NtlmTime;
+use rusqlite::{params, Connection};
+
+/// Tables of the results database. `hosts` keeps the last observation of
+/// each target and protocol, whereas `observations` keeps all of them, in
+/// order to compare the results of different scans. The `os` and
+/// `hostnames` columns keep the lists as in the CSV output.
+const SCHEMA: &'static str = "
+CREATE TABLE IF NOT EXISTS scans (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    time TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS hosts (
+    target TEXT NOT NULL,
+    protocol TEXT NOT NULL,
+    ip TEXT,
+    hostname TEXT,
+    hostnames TEXT,
+    port INTEGER,
+    nb_computer TEXT,
+    nb_domain TEXT,
+    dns_computer TEXT,
+    dns_domain TEXT,
+    dns_tree TEXT,
+    version TEXT,
+    os TEXT,
+    first_scan INTEGER NOT NULL REFERENCES scans(id),
+    last_scan INTEGER NOT NULL REFERENCES scans(id),
+    first_seen TEXT NOT NULL,
+    last_seen TEXT NOT NULL,
+    PRIMARY KEY (target, protocol)
+);
+
+CREATE TABLE IF NOT EXISTS observations (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    scan INTEGER NOT NULL REFERENCES scans(id),
+    time TEXT NOT NULL,
+    target TEXT NOT NULL,
+    protocol TEXT NOT NULL,
+    ip TEXT,
+    hostname TEXT,
+    hostnames TEXT,
+    port INTEGER,
+    nb_computer TEXT,
+    nb_domain TEXT,
+    dns_computer TEXT,
+    dns_domain TEXT,
+    dns_tree TEXT,
+    version TEXT,
+    os TEXT,
+    server_time TEXT,
+    skew REAL,
+    challenge TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS observations_target
+    ON observations (target, protocol);
+";
+
+const UPSERT_HOST: &'static str = "
+INSERT INTO hosts (
+    target, protocol, ip, hostname, port, nb_computer, nb_domain,
+    dns_computer, dns_domain, dns_tree, version, os,
+    first_scan, last_scan, first_seen, last_seen, hostnames
+) VALUES (
+    ?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?13, ?14, ?14, ?15
+)
+ON CONFLICT (target, protocol) DO UPDATE SET
+    ip = excluded.ip,
+    hostname = excluded.hostname,
+    hostnames = excluded.hostnames,
+    port = excluded.port,
+    nb_computer = excluded.nb_computer,
+    nb_domain = excluded.nb_domain,
+    dns_computer = excluded.dns_computer,
+    dns_domain = excluded.dns_domain,
+    dns_tree = excluded.dns_tree,
+    version = excluded.version,
+    os = excluded.os,
+    last_scan = excluded.last_scan,
+    last_seen = excluded.last_seen
+";
+
+const INSERT_OBSERVATION: &'static str = "
+INSERT INTO observations (
+    scan, time, target, protocol, ip, hostname, port, nb_computer,
+    nb_domain, dns_computer, dns_domain, dns_tree, version, os,
+    server_time, skew, challenge, hostnames
+) VALUES (
+    ?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17,
+    ?18
+)";
+
+/// Save the challenges in a SQLite database, registering a new scan with
+/// the given time. Hosts already in the database are updated, but their
+/// previous observations are kept.
+pub fn save_db(
+    out_file: &str,
+    chs: &Vec<Challenge>,
+    scan_time: &NtlmTime,
+) -> Result<(), String> {
+    let db_err = |e: rusqlite::Error| {
+        format!("Error saving results in database '{}': {}", out_file, e)
+    };
+
+    let mut conn = Connection::open(out_file).map_err(db_err)?;
+    conn.execute_batch(SCHEMA).map_err(db_err)?;
+
+    let tx = conn.transaction().map_err(db_err)?;
+    tx.execute(
+        "INSERT INTO scans (time) VALUES (?1)",
+        params![scan_time.to_rfc3339()],
+    )
+    .map_err(db_err)?;
+    let scan = tx.last_insert_rowid();
+
+    for ch in chs {
+        let json = JsonChallenge::from_challenge(ch);
+        let time = ch.received.unwrap_or(*scan_time).to_rfc3339();
+        let protocol = json.protocol.clone().unwrap_or_default();
+        let os = json.os_names.as_ref().map(|names| names.join(" | "));
+        let hostnames =
+            Some(json.hostnames.join(" | ")).filter(|h| h.len() != 0);
+
+        tx.execute(
+            UPSERT_HOST,
+            params![
+                json.target,
+                protocol,
+                json.ip,
+                json.hostname,
+                json.port,
+                json.nb_computer,
+                json.nb_domain,
+                json.dns_computer,
+                json.dns_domain,
+                json.dns_tree,
+                json.version,
+                os,
+                scan,
+                time,
+                hostnames,
+            ],
+        )
+        .map_err(db_err)?;
+
+        tx.execute(
+            INSERT_OBSERVATION,
+            params![
+                scan,
+                time,
+                json.target,
+                protocol,
+                json.ip,
+                json.hostname,
+                json.port,
+                json.nb_computer,
+                json.nb_domain,
+                json.dns_computer,
+                json.dns_domain,
+                json.dns_tree,
+                json.version,
+                os,
+                json.server_time,
+                json.skew,
+                serde_json::to_string(&json).map_err(|e| e.to_string())?,
+                hostnames,
+            ],
+        )
+        .map_err(db_err)?;
+    }
+
+    tx.commit().map_err(db_err)?;
+
+    info!("Save challenges in database '{}'", out_file);
+
+    return Ok(());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::challenge::{ChallengeTarget, Host};
+    use ntlm::{ChallengeMsg, Version};
+
+    fn windows7_challenge() -> Challenge {
+        let host = Host::new("10.0.0.1".parse().unwrap(), vec![]);
+        let msg = ChallengeMsg {
+            version: Some(Version::windows7_7601()),
+            ..Default::default()
+        };
+        return Challenge::new(ChallengeTarget::Host(host), msg);
+    }
+
+    #[test]
+    fn save_os_as_csv() {
+        let path = std::env::temp_dir()
+            .join(format!("ntlm-info-os-{}.db", std::process::id()));
+        let db_file = path.to_str().unwrap();
+        let chs = vec![windows7_challenge()];
+        let result = save_db(db_file, &chs, &ntlm::time::now());
+
+        let conn = Connection::open(db_file).unwrap();
+        let os: Option<String> = conn
+            .query_row("SELECT os FROM hosts", params![], |row| row.get(0))
+            .unwrap();
+        drop(conn);
+        std::fs::remove_file(&path).unwrap();
+        result.unwrap();
+
+        let fields = super::super::challenge_fields(&chs[0]);
+        assert_eq!(os.as_deref(), fields["os"].as_str());
+        assert!(!os.unwrap().contains("7601"));
+    }
+
+    #[test]
+    fn save_merged_hostnames() {
+        let path = std::env::temp_dir()
+            .join(format!("ntlm-info-hostnames-{}.db", std::process::id()));
+        let db_file = path.to_str().unwrap();
+        let host = Host::new(
+            "10.0.0.1".parse().unwrap(),
+            vec!["srv01".to_string(), "srv01.contoso.local".to_string()],
+        );
+        let chs = vec![Challenge::new(
+            ChallengeTarget::Host(host),
+            ChallengeMsg::default(),
+        )];
+        let result = save_db(db_file, &chs, &ntlm::time::now());
+
+        let conn = Connection::open(db_file).unwrap();
+        let query = |sql: &str| -> Option<String> {
+            conn.query_row(sql, params![], |row| row.get(0)).unwrap()
+        };
+        let hosts = query("SELECT hostnames FROM hosts");
+        let observations = query("SELECT hostnames FROM observations");
+        drop(conn);
+        std::fs::remove_file(&path).unwrap();
+        result.unwrap();
+
+        let expected = Some("srv01 | srv01.contoso.local".to_string());
+        assert_eq!(hosts, expected);
+        assert_eq!(observations, expected);
+    }
+}
@@ -0,0 +1,207 @@
+use crate::challenge::Challenge;
+use log::info;
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::Write;
+use std::net::IpAddr;
+
+/// Names of a host discovered in its challenge.
+struct HostNames {
+    ip: IpAddr,
+    dns_computer: Option<String>,
+    nb_computer: Option<String>,
+}
+
+/// Retrieves the names of each IP, only once per IP, skipping those
+/// challenges without computer names.
+fn host_names(chs: &Vec<Challenge>) -> Vec<HostNames> {
+    let mut ips = HashSet::new();
+    let mut hosts = Vec::new();
+
+    for ch in chs {
+        let ip = match ch.ip() {
+            Some(ip) => ip,
+            None => continue,
+        };
+
+        let dns_computer = ch
+            .challenge
+            .dns_computer_name()
+            .filter(|n| n.len() != 0)
+            .map(|n| n.to_lowercase());
+        let nb_computer = ch
+            .challenge
+            .nb_computer_name()
+            .filter(|n| n.len() != 0)
+            .map(|n| n.to_string());
+
+        if dns_computer.is_none() && nb_computer.is_none() {
+            continue;
+        }
+
+        if !ips.insert(ip) {
+            continue;
+        }
+
+        hosts.push(HostNames {
+            ip,
+            dns_computer,
+            nb_computer,
+        });
+    }
+
+    return hosts;
+}
+
+/// Save the discovered names as /etc/hosts lines, mapping each IP to its
+/// DNS computer name and NetBIOS computer name.
+pub fn save_hosts(out_file: &str, chs: &Vec<Challenge>) -> Result<(), String> {
+    return write_file(out_file, &hosts_lines(chs));
+}
+
+fn hosts_lines(chs: &Vec<Challenge>) -> String {
+    let mut lines = String::new();
+    for host in host_names(chs) {
+        let mut names = Vec::new();
+        if let Some(dns_computer) = host.dns_computer {
+            names.push(dns_computer);
+        }
+        if let Some(nb_computer) = host.nb_computer {
+            if !names.iter().any(|n| n.eq_ignore_ascii_case(&nb_computer)) {
+                names.push(nb_computer);
+            }
+        }
+        lines.push_str(&format!("{}\t{}\n", host.ip, names.join(" ")));
+    }
+
+    return lines;
+}
+
+/// Save the discovered DNS names as BIND A/AAAA and PTR records.
+pub fn save_zone(out_file: &str, chs: &Vec<Challenge>) -> Result<(), String> {
+    return write_file(out_file, &zone_records(chs));
+}
+
+fn zone_records(chs: &Vec<Challenge>) -> String {
+    let mut records = Vec::new();
+    let mut ptrs = Vec::new();
+    for host in host_names(chs) {
+        let dns_computer = match host.dns_computer {
+            Some(dns_computer) => dns_computer,
+            None => continue,
+        };
+
+        let rtype = match host.ip {
+            IpAddr::V4(_) => "A",
+            IpAddr::V6(_) => "AAAA",
+        };
+
+        records
+            .push(format!("{}.\tIN\t{}\t{}\n", dns_computer, rtype, host.ip));
+        ptrs.push(format!(
+            "{}\tIN\tPTR\t{}.\n",
+            reverse_name(&host.ip),
+            dns_computer
+        ));
+    }
+
+    records.extend(ptrs);
+    return records.concat();
+}
+
+/// Returns the name used in PTR records for the IP.
+fn reverse_name(ip: &IpAddr) -> String {
+    match ip {
+        IpAddr::V4(ip) => {
+            let o = ip.octets();
+            format!("{}.{}.{}.{}.in-addr.arpa.", o[3], o[2], o[1], o[0])
+        }
+        IpAddr::V6(ip) => {
+            let mut name = String::new();
+            for b in ip.octets().iter().rev() {
+                name.push_str(&format!("{:x}.{:x}.", b & 0xf, b >> 4));
+            }
+            name.push_str("ip6.arpa.");
+            name
+        }
+    }
+}
+
+fn write_file(out_file: &str, data: &str) -> Result<(), String> {
+    let mut file = File::create(out_file)
+        .map_err(|e| format!("Error opening '{}': {}", out_file, e))?;
+
+    file.write_all(data.as_bytes())
+        .map_err(|e| format!("Error writing '{}': {}", out_file, e))?;
+
+    info!("Save challenges in '{}'", out_file);
+
+    return Ok(());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::challenge::{ChallengeTarget, Host};
+    use ntlm::{AvPair, ChallengeMsg};
+
+    fn challenge(ip: &str, dns: &str, nb: &str) -> Challenge {
+        let mut msg = ChallengeMsg::default();
+        msg.target_info = vec![
+            AvPair::NbComputerName(nb.to_string()),
+            AvPair::DnsComputerName(dns.to_string()),
+            AvPair::EOL,
+        ]
+        .into();
+
+        let host = Host::new(ip.parse().unwrap(), vec![]);
+        return Challenge::new(ChallengeTarget::Host(host), msg);
+    }
+
+    #[test]
+    fn hosts_lines_once_per_ip() {
+        let chs = vec![
+            challenge("10.0.0.1", "WS01.contoso.local", "WS01"),
+            challenge("10.0.0.1", "other.contoso.local", "OTHER"),
+            challenge("10.0.0.2", "", "SRV02"),
+            challenge("10.0.0.3", "dc01", "DC01"),
+            challenge("10.0.0.4", "", ""),
+        ];
+        assert_eq!(
+            hosts_lines(&chs),
+            "10.0.0.1\tws01.contoso.local WS01\n\
+             10.0.0.2\tSRV02\n\
+             10.0.0.3\tdc01\n"
+        );
+    }
+
+    #[test]
+    fn zone_records_of_dns_names() {
+        let chs = vec![
+            challenge("10.0.0.1", "WS01.contoso.local", "WS01"),
+            challenge("10.0.0.2", "", "SRV02"),
+            challenge("2001:db8::1", "ws06.contoso.local", "WS06"),
+        ];
+        assert_eq!(
+            zone_records(&chs),
+            "ws01.contoso.local.\tIN\tA\t10.0.0.1\n\
+             ws06.contoso.local.\tIN\tAAAA\t2001:db8::1\n\
+             1.0.0.10.in-addr.arpa.\tIN\tPTR\tws01.contoso.local.\n\
+             1.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.8.b.d.0.1.0.0.2.\
+             ip6.arpa.\tIN\tPTR\tws06.contoso.local.\n"
+        );
+    }
+
+    #[test]
+    fn reverse_names_of_ipv4_and_ipv6() {
+        assert_eq!(
+            reverse_name(&"192.168.1.20".parse().unwrap()),
+            "20.1.168.192.in-addr.arpa."
+        );
+        assert_eq!(
+            reverse_name(&"2001:db8::567:89ab".parse().unwrap()),
+            "b.a.9.8.7.6.5.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.8.b.d.0.1.0.0.2.\
+             ip6.arpa."
+        );
+    }
+}
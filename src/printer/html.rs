@@ -0,0 +1,318 @@
+use super::summary::{os_version, Domains};
+use super::{challenge_fields, field_to_string};
+use crate::challenge::{Challenge, Failure};
+use log::info;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::Write;
+
+const STYLE: &'static str = r#"
+body { font-family: sans-serif; margin: 2em; color: #222; }
+h1 { font-size: 1.6em; }
+h2 { font-size: 1.2em; margin-top: 2em; border-bottom: 1px solid #ccc; }
+table { border-collapse: collapse; font-size: 0.9em; }
+th, td { border: 1px solid #ccc; padding: 4px 8px; text-align: left; }
+th { background: #eee; }
+#hosts th { cursor: pointer; user-select: none; }
+#hosts th.asc::after { content: " \25B2"; }
+#hosts th.desc::after { content: " \25BC"; }
+#filter { margin-bottom: 1em; padding: 4px; width: 30em; }
+.bar { background: #4a7ebb; height: 1em; }
+"#;
+
+/// Sorts the hosts table by the clicked column and filters the rows that
+/// contain the text of the filter box.
+const SCRIPT: &'static str = r#"
+(function () {
+  var table = document.getElementById("hosts");
+  var body = table.tBodies[0];
+  var headers = table.tHead.rows[0].cells;
+  for (var i = 0; i < headers.length; i++) {
+    headers[i].addEventListener("click", sortBy.bind(null, i));
+  }
+
+  function cellValue(row, i) {
+    return row.cells[i].textContent.trim();
+  }
+
+  function compare(a, b) {
+    var na = Number(a), nb = Number(b);
+    if (a !== "" && b !== "" && !isNaN(na) && !isNaN(nb)) {
+      return na - nb;
+    }
+    return a.localeCompare(b, undefined, { numeric: true });
+  }
+
+  function sortBy(i) {
+    var asc = !headers[i].classList.contains("asc");
+    for (var j = 0; j < headers.length; j++) {
+      headers[j].classList.remove("asc", "desc");
+    }
+    headers[i].classList.add(asc ? "asc" : "desc");
+    var rows = Array.prototype.slice.call(body.rows);
+    rows.sort(function (a, b) {
+      var r = compare(cellValue(a, i), cellValue(b, i));
+      return asc ? r : -r;
+    });
+    rows.forEach(function (row) { body.appendChild(row); });
+  }
+
+  document.getElementById("filter").addEventListener("input", function () {
+    var text = this.value.toLowerCase();
+    var shown = 0;
+    Array.prototype.forEach.call(body.rows, function (row) {
+      var match = row.textContent.toLowerCase().indexOf(text) !== -1;
+      row.style.display = match ? "" : "none";
+      if (match) { shown++; }
+    });
+    document.getElementById("shown").textContent = shown;
+  });
+})();
+"#;
+
+/// Save the results in a single HTML file, without external resources,
+/// with a table of hosts that can be sorted and filtered, the hosts of
+/// each domain, the histogram of OS versions and the failed targets.
+pub fn save_html(
+    out_file: &str,
+    chs: &Vec<Challenge>,
+    failures: &Vec<Failure>,
+    columns: &[String],
+) -> Result<(), String> {
+    let mut html = String::new();
+    html.push_str("<!DOCTYPE html>\n<html>\n<head>\n");
+    html.push_str("<meta charset=\"utf-8\">\n");
+    html.push_str("<title>ntlm-info report</title>\n");
+    html.push_str(&format!("<style>{}</style>\n", STYLE));
+    html.push_str("</head>\n<body>\n");
+    html.push_str("<h1>ntlm-info report</h1>\n");
+    html.push_str(&format!(
+        "<p>Generated: {}<br>Hosts: {}<br>Errors: {}</p>\n",
+        escape(&ntlm::time::now().to_rfc3339()),
+        chs.len(),
+        failures.len()
+    ));
+
+    html.push_str(&hosts_section(chs, columns));
+    html.push_str(&domains_section(chs));
+    html.push_str(&versions_section(chs));
+    html.push_str(&errors_section(failures));
+
+    html.push_str(&format!("<script>{}</script>\n", SCRIPT));
+    html.push_str("</body>\n</html>\n");
+
+    let mut file = File::create(out_file)
+        .map_err(|e| format!("Error opening '{}': {}", out_file, e))?;
+
+    file.write_all(html.as_bytes())
+        .map_err(|e| format!("Error writing '{}': {}", out_file, e))?;
+
+    info!("Save challenges in '{}'", out_file);
+
+    return Ok(());
+}
+
+fn hosts_section(chs: &Vec<Challenge>, columns: &[String]) -> String {
+    let mut html = String::new();
+    html.push_str("<h2>Hosts</h2>\n");
+    html.push_str(&format!(
+        "<input id=\"filter\" type=\"search\" placeholder=\"Filter\"> \
+         <span id=\"shown\">{}</span> hosts\n",
+        chs.len()
+    ));
+    html.push_str("<table id=\"hosts\">\n<thead><tr>");
+    for column in columns {
+        html.push_str(&format!("<th>{}</th>", escape(column)));
+    }
+    html.push_str("</tr></thead>\n<tbody>\n");
+
+    for ch in chs {
+        let fields = challenge_fields(ch);
+        html.push_str("<tr>");
+        for column in columns {
+            let value =
+                field_to_string(fields.get(column).unwrap_or(&Value::Null));
+            html.push_str(&format!("<td>{}</td>", escape(&value)));
+        }
+        html.push_str("</tr>\n");
+    }
+    html.push_str("</tbody>\n</table>\n");
+
+    return html;
+}
+
+fn domains_section(chs: &Vec<Challenge>) -> String {
+    let Domains { domains, no_domain } = Domains::new(chs);
+
+    let mut html = String::new();
+    html.push_str("<h2>Domains</h2>\n");
+    html.push_str(
+        "<table>\n<thead><tr><th>Domain</th><th>NetBIOS</th>\
+         <th>Forest</th><th>Hosts</th><th>Versions</th></tr></thead>\n\
+         <tbody>\n",
+    );
+
+    for (name, domain) in domains.iter() {
+        let forests: Vec<&str> =
+            domain.forests.iter().map(|f| f.as_str()).collect();
+        let versions: Vec<String> = domain
+            .versions
+            .iter()
+            .map(|(version, count)| escape(&format!("{}: {}", version, count)))
+            .collect();
+
+        html.push_str(&format!(
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td>\
+             <td>{}</td></tr>\n",
+            escape(name),
+            escape(domain.nb_domain.as_deref().unwrap_or("")),
+            escape(&forests.join(" | ")),
+            domain.hosts,
+            versions.join("<br>")
+        ));
+    }
+    html.push_str("</tbody>\n</table>\n");
+
+    if no_domain.len() != 0 {
+        html.push_str("<h2>Hosts without domain</h2>\n<ul>\n");
+        for ch in no_domain {
+            let name = ch.challenge.nb_computer_name();
+            html.push_str(&format!(
+                "<li>{} {}</li>\n",
+                escape(&ch.target.to_string()),
+                escape(&name.map(|n| format!("({})", n)).unwrap_or_default())
+            ));
+        }
+        html.push_str("</ul>\n");
+    }
+
+    return html;
+}
+
+fn versions_section(chs: &Vec<Challenge>) -> String {
+    let mut versions: BTreeMap<String, usize> = BTreeMap::new();
+    for ch in chs {
+        *versions.entry(os_version(ch)).or_default() += 1;
+    }
+    let max = versions.values().max().cloned().unwrap_or(1);
+
+    let mut html = String::new();
+    html.push_str("<h2>OS versions</h2>\n<table>\n");
+    for (version, count) in versions.iter() {
+        html.push_str(&format!(
+            "<tr><td>{}</td><td>{}</td><td style=\"width: 20em\">\
+             <div class=\"bar\" style=\"width: {}%\"></div></td></tr>\n",
+            escape(version),
+            count,
+            count * 100 / max
+        ));
+    }
+    html.push_str("</table>\n");
+
+    return html;
+}
+
+fn errors_section(failures: &Vec<Failure>) -> String {
+    let mut html = String::new();
+    html.push_str("<h2>Errors</h2>\n");
+    if failures.len() == 0 {
+        html.push_str("<p>None</p>\n");
+        return html;
+    }
+
+    html.push_str(
+        "<table>\n<thead><tr><th>Target</th><th>Error</th>\
+         <th>Message</th></tr></thead>\n<tbody>\n",
+    );
+    for failure in failures {
+        html.push_str(&format!(
+            "<tr><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+            escape(&failure.target),
+            failure.error.category(),
+            escape(failure.error.message())
+        ));
+    }
+    html.push_str("</tbody>\n</table>\n");
+
+    return html;
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    return escaped;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::challenge::ChallengeTarget;
+    use crate::error::Error;
+    use ntlm::{AvPair, ChallengeMsg};
+
+    const HOSTILE: &'static str = "<script>alert('x&y')</script>\"";
+    const ESCAPED: &'static str =
+        "&lt;script&gt;alert(&#39;x&amp;y&#39;)&lt;/script&gt;&quot;";
+
+    fn hostile_challenge() -> Challenge {
+        let mut msg = ChallengeMsg::default();
+        msg.target_info = vec![
+            AvPair::NbDomainName(HOSTILE.to_string()),
+            AvPair::NbComputerName(format!("pc{}", HOSTILE)),
+            AvPair::DnsDomainName(HOSTILE.to_string()),
+            AvPair::DnsTreeName(HOSTILE.to_string()),
+            AvPair::EOL,
+        ]
+        .into();
+        let target = ChallengeTarget::Url(format!("http://ws/{}", HOSTILE));
+        return Challenge::new(target, msg);
+    }
+
+    #[test]
+    fn escape_html_characters() {
+        assert_eq!(escape(HOSTILE), ESCAPED);
+        assert_eq!(escape("ws01.contoso.local"), "ws01.contoso.local");
+    }
+
+    #[test]
+    fn escape_hostile_names() {
+        let chs = vec![hostile_challenge()];
+        let columns = vec!["nb_computer".to_string(), "dns_domain".to_string()];
+
+        let hosts = hosts_section(&chs, &columns);
+        assert!(hosts
+            .contains(&format!("<td>pc{}</td><td>{}</td>", ESCAPED, ESCAPED)));
+
+        let domains = domains_section(&chs);
+        assert!(domains.contains(&format!(
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>1</td>",
+            ESCAPED.to_lowercase(),
+            ESCAPED,
+            ESCAPED.to_lowercase()
+        )));
+
+        let failures = vec![Failure::new(
+            HOSTILE.to_string(),
+            Error::Protocol(HOSTILE.to_string()),
+        )];
+        let errors = errors_section(&failures);
+        assert!(errors.contains(&format!(
+            "<tr><td>{}</td><td>protocol</td><td>{}</td></tr>",
+            ESCAPED, ESCAPED
+        )));
+
+        for html in [hosts, domains, errors].iter() {
+            assert!(!html.contains("<script>"));
+        }
+    }
+}
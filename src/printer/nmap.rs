@@ -0,0 +1,319 @@
+use super::summary::os_version;
+use super::version_to_string;
+use crate::challenge::{Challenge, Protocol};
+use log::info;
+use ntlm::time::NtlmTime;
+use std::collections::{BTreeMap, HashSet};
+use std::fs::File;
+use std::io::Write;
+use std::net::IpAddr;
+
+/// Version of the nmap XML format that is generated.
+const XML_OUTPUT_VERSION: &'static str = "1.05";
+
+/// Save the challenges in nmap XML format, so they can be imported by
+/// the tools that understand nmap results. Each challenge is shown as the
+/// output of the nmap script that retrieves the same information,
+/// smb-os-discovery for SMB and http-ntlm-info for HTTP.
+pub fn save_nmap(
+    out_file: &str,
+    chs: &Vec<Challenge>,
+    scan_time: &NtlmTime,
+) -> Result<(), String> {
+    let end_time = ntlm::time::now();
+    let args: Vec<String> = std::env::args().collect();
+
+    // challenges of the same IP are grouped in the same host
+    let mut hosts: BTreeMap<IpAddr, Vec<&Challenge>> = BTreeMap::new();
+    for ch in chs {
+        if let Some(ip) = ch.ip() {
+            hosts.entry(ip).or_default().push(ch);
+        }
+    }
+
+    let mut ports: Vec<u16> = chs.iter().filter_map(|ch| ch.port()).collect();
+    ports.sort();
+    ports.dedup();
+    let services: Vec<String> = ports.iter().map(|p| p.to_string()).collect();
+
+    let mut xml = String::new();
+    xml.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str("<!DOCTYPE nmaprun>\n");
+    xml.push_str(&format!(
+        "<nmaprun scanner=\"nmap\" args=\"{}\" start=\"{}\" \
+         startstr=\"{}\" version=\"{}\" xmloutputversion=\"{}\">\n",
+        escape(&args.join(" ")),
+        scan_time.timestamp(),
+        escape(&time_str(scan_time)),
+        env!("CARGO_PKG_VERSION"),
+        XML_OUTPUT_VERSION
+    ));
+    xml.push_str(&format!(
+        "<scaninfo type=\"connect\" protocol=\"tcp\" numservices=\"{}\" \
+         services=\"{}\"/>\n",
+        services.len(),
+        services.join(",")
+    ));
+
+    for (ip, chs) in hosts.iter() {
+        xml.push_str(&host_to_xml(ip, chs, scan_time, &end_time));
+    }
+
+    xml.push_str(&format!(
+        "<runstats><finished time=\"{}\" timestr=\"{}\" elapsed=\"{}\" \
+         exit=\"success\"/><hosts up=\"{}\" down=\"0\" total=\"{}\"/>\
+         </runstats>\n",
+        end_time.timestamp(),
+        escape(&time_str(&end_time)),
+        (end_time - *scan_time).num_seconds(),
+        hosts.len(),
+        hosts.len()
+    ));
+    xml.push_str("</nmaprun>\n");
+
+    let mut file = File::create(out_file)
+        .map_err(|e| format!("Error opening '{}': {}", out_file, e))?;
+
+    file.write_all(xml.as_bytes())
+        .map_err(|e| format!("Error writing '{}': {}", out_file, e))?;
+
+    info!("Save challenges in '{}'", out_file);
+
+    return Ok(());
+}
+
+fn host_to_xml(
+    ip: &IpAddr,
+    chs: &Vec<&Challenge>,
+    start_time: &NtlmTime,
+    end_time: &NtlmTime,
+) -> String {
+    let mut xml = String::new();
+    xml.push_str(&format!(
+        "<host starttime=\"{}\" endtime=\"{}\">\
+         <status state=\"up\" reason=\"user-set\" reason_ttl=\"0\"/>\n",
+        start_time.timestamp(),
+        end_time.timestamp()
+    ));
+
+    let addrtype = match ip {
+        IpAddr::V4(_) => "ipv4",
+        IpAddr::V6(_) => "ipv6",
+    };
+    xml.push_str(&format!(
+        "<address addr=\"{}\" addrtype=\"{}\"/>\n",
+        ip, addrtype
+    ));
+
+    let mut hostnames = Vec::new();
+    for ch in chs.iter() {
+        for name in ch.hostnames() {
+            hostnames.push((name, "user"));
+        }
+        if let Some(name) = ch.challenge.dns_computer_name() {
+            hostnames.push((name.to_lowercase(), "user"));
+        }
+        if let Some(dns) = &ch.dns {
+            for name in dns.ptr.iter() {
+                hostnames.push((name.clone(), "PTR"));
+            }
+        }
+        if let Some(name) = ch.challenge.nb_computer_name() {
+            hostnames.push((name.to_string(), "user"));
+        }
+    }
+    let mut seen = HashSet::new();
+    hostnames.retain(|h| seen.insert(h.clone()));
+
+    xml.push_str("<hostnames>\n");
+    for (name, name_type) in hostnames {
+        xml.push_str(&format!(
+            "<hostname name=\"{}\" type=\"{}\"/>\n",
+            escape(&name),
+            name_type
+        ));
+    }
+    xml.push_str("</hostnames>\n");
+
+    xml.push_str("<ports>\n");
+    for ch in chs.iter() {
+        let port = match ch.port() {
+            Some(port) => port,
+            None => continue,
+        };
+
+        xml.push_str(&format!(
+            "<port protocol=\"tcp\" portid=\"{}\">\
+             <state state=\"open\" reason=\"syn-ack\" reason_ttl=\"0\"/>\
+             <service name=\"{}\" method=\"probed\" conf=\"10\"/>\n",
+            port,
+            service_name(ch, port)
+        ));
+        xml.push_str(&script_to_xml(ch));
+        xml.push_str("</port>\n");
+    }
+    xml.push_str("</ports>\n");
+    xml.push_str("</host>\n");
+
+    return xml;
+}
+
+fn service_name(ch: &Challenge, port: u16) -> &'static str {
+    match (ch.protocol, port) {
+        (Some(Protocol::Smb), 139) => "netbios-ssn",
+        (Some(Protocol::Smb), _) => "microsoft-ds",
+        (Some(Protocol::Http), _) => match ch.target.to_string() {
+            url if url.starts_with("https:") => "https",
+            _ => "http",
+        },
+        (None, _) => "unknown",
+    }
+}
+
+/// Returns the output of the nmap script corresponding to the protocol,
+/// with the same keys used by nmap.
+fn script_to_xml(ch: &Challenge) -> String {
+    let nt = &ch.challenge;
+    // (key, label, value), labels are only different in smb-os-discovery
+    let mut elems: Vec<(&str, &str, String)> = Vec::new();
+
+    let id = match ch.protocol {
+        Some(Protocol::Smb) => {
+            if nt.version.is_some() {
+                elems.push(("os", "OS", os_version(ch)));
+            }
+            if let Some(name) = nt.dns_computer_name() {
+                let name = name.split('.').next().unwrap_or(name);
+                elems.push(("", "Computer name", name.to_string()));
+            }
+            if let Some(name) = nt.nb_computer_name() {
+                elems.push(("server", "NetBIOS computer name", name.clone()));
+            }
+            if let Some(name) = nt.dns_domain_name() {
+                elems.push(("domain_dns", "Domain name", name.clone()));
+            }
+            if let Some(name) = nt.dns_tree_name() {
+                elems.push(("forest_dns", "Forest name", name.clone()));
+            }
+            if let Some(name) = nt.dns_computer_name() {
+                elems.push(("fqdn", "FQDN", name.clone()));
+            }
+            if let Some(name) = nt.nb_domain_name() {
+                elems.push(("domain", "NetBIOS domain name", name.clone()));
+            }
+            if let Some(time) = ch.server_time() {
+                elems.push(("date", "System time", time.to_rfc3339()));
+            }
+            "smb-os-discovery"
+        }
+        _ => {
+            let mut fields = Vec::new();
+            if let Some(name) = &nt.target_name {
+                fields.push(("Target_Name", name.clone()));
+            }
+            if let Some(name) = nt.nb_domain_name() {
+                fields.push(("NetBIOS_Domain_Name", name.clone()));
+            }
+            if let Some(name) = nt.nb_computer_name() {
+                fields.push(("NetBIOS_Computer_Name", name.clone()));
+            }
+            if let Some(name) = nt.dns_domain_name() {
+                fields.push(("DNS_Domain_Name", name.clone()));
+            }
+            if let Some(name) = nt.dns_computer_name() {
+                fields.push(("DNS_Computer_Name", name.clone()));
+            }
+            if let Some(name) = nt.dns_tree_name() {
+                fields.push(("DNS_Tree_Name", name.clone()));
+            }
+            if let Some(version) = &nt.version {
+                fields.push(("Product_Version", version_to_string(version)));
+            }
+            elems = fields.into_iter().map(|(k, v)| (k, k, v)).collect();
+            match ch.protocol {
+                Some(Protocol::Http) => "http-ntlm-info",
+                _ => "ntlm-info",
+            }
+        }
+    };
+
+    let output: String = elems
+        .iter()
+        .map(|(_, label, value)| format!("\n  {}: {}", label, value))
+        .collect();
+
+    let mut xml =
+        format!("<script id=\"{}\" output=\"{}\">", id, escape(&output));
+    for (key, _, value) in elems.iter().filter(|(k, _, _)| k.len() != 0) {
+        xml.push_str(&format!(
+            "<elem key=\"{}\">{}</elem>",
+            key,
+            escape(value)
+        ));
+    }
+    xml.push_str("</script>\n");
+
+    return xml;
+}
+
+fn time_str(time: &NtlmTime) -> String {
+    return time.format("%a %b %e %H:%M:%S %Y").to_string();
+}
+
+/// Escapes the text to be used in XML attributes and elements.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            '\n' => escaped.push_str("&#xa;"),
+            '\0' => escaped.push_str("\\x00"),
+            c => escaped.push(c),
+        }
+    }
+    return escaped;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::challenge::{ChallengeTarget, Host};
+    use crate::dns::{DnsCheck, DnsStatus};
+    use ntlm::{AvPair, ChallengeMsg};
+
+    #[test]
+    fn ptr_hostnames_only_from_dns() {
+        let ip: IpAddr = "10.0.0.1".parse().unwrap();
+        let mut msg = ChallengeMsg::default();
+        msg.target_info =
+            vec![AvPair::DnsComputerName("SRV01.contoso.local".to_string())]
+                .into();
+        let mut ch = Challenge::new(
+            ChallengeTarget::Host(Host::new(ip, vec!["web".to_string()])),
+            msg,
+        );
+
+        let now = ntlm::time::now();
+        let xml = host_to_xml(&ip, &vec![&ch], &now, &now);
+        assert!(xml.contains("<hostname name=\"web\" type=\"user\"/>"));
+        assert!(xml.contains(
+            "<hostname name=\"srv01.contoso.local\" type=\"user\"/>"
+        ));
+        assert!(!xml.contains("PTR"));
+
+        ch.dns = Some(DnsCheck {
+            ptr: vec!["old.contoso.local".to_string()],
+            forward: vec![ip],
+            status: DnsStatus::StalePtr,
+        });
+        let xml = host_to_xml(&ip, &vec![&ch], &now, &now);
+        assert!(
+            xml.contains("<hostname name=\"old.contoso.local\" type=\"PTR\"/>")
+        );
+        assert!(!xml.contains("srv01.contoso.local\" type=\"PTR"));
+    }
+}
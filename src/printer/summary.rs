@@ -0,0 +1,221 @@
+use super::version_to_string;
+use crate::challenge::Challenge;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Hosts of the same domain.
+#[derive(Default)]
+pub struct DomainSummary {
+    pub nb_domain: Option<String>,
+    pub forests: BTreeSet<String>,
+    pub hosts: usize,
+    pub versions: BTreeMap<String, usize>,
+}
+
+/// Results grouped by domain, indexed by the DNS domain name or, if
+/// missing, the NetBIOS domain name.
+pub struct Domains<'a> {
+    pub domains: BTreeMap<String, DomainSummary>,
+
+    /// Hosts without domain (workgroup or standalone), which report their
+    /// computer name as domain.
+    pub no_domain: Vec<&'a Challenge>,
+}
+
+impl<'a> Domains<'a> {
+    pub fn new(chs: &'a Vec<Challenge>) -> Self {
+        let mut domains: BTreeMap<String, DomainSummary> = BTreeMap::new();
+        let mut no_domain = Vec::new();
+
+        for ch in chs {
+            let nb_computer = ch.challenge.nb_computer_name();
+            let nb_domain = ch
+                .challenge
+                .nb_domain_name()
+                .filter(|d| d.len() != 0)
+                .filter(|d| match nb_computer {
+                    Some(c) => !d.eq_ignore_ascii_case(c),
+                    None => true,
+                });
+
+            let nb_domain = match nb_domain {
+                Some(nb_domain) => nb_domain,
+                None => {
+                    no_domain.push(ch);
+                    continue;
+                }
+            };
+
+            let name = match ch.challenge.dns_domain_name() {
+                Some(dns_domain) if dns_domain.len() != 0 => {
+                    dns_domain.to_lowercase()
+                }
+                _ => nb_domain.to_uppercase(),
+            };
+
+            let domain = domains.entry(name).or_default();
+            domain.nb_domain.get_or_insert(nb_domain.clone());
+            domain.hosts += 1;
+
+            if let Some(tree) = ch.challenge.dns_tree_name() {
+                if tree.len() != 0 {
+                    domain.forests.insert(tree.to_lowercase());
+                }
+            }
+
+            *domain.versions.entry(os_version(ch)).or_default() += 1;
+        }
+
+        return Self { domains, no_domain };
+    }
+}
+
+/// Returns the version of the host together with its possible OS names.
+pub fn os_version(ch: &Challenge) -> String {
+    match &ch.challenge.version {
+        Some(version) => match version.os_names().len() {
+            0 => version_to_string(version),
+            _ => format!(
+                "{} ({})",
+                version_to_string(version),
+                version.os_names().join(" | ")
+            ),
+        },
+        None => "Unknown".to_string(),
+    }
+}
+
+/// Print the results grouped by domain, showing the number of hosts, the
+/// forests and the OS versions of each one, followed by the hosts that
+/// don't belong to any domain (workgroup or standalone).
+pub fn print_summary(chs: &Vec<Challenge>) {
+    let Domains { domains, no_domain } = Domains::new(chs);
+
+    let forests: BTreeSet<&String> =
+        domains.values().flat_map(|d| d.forests.iter()).collect();
+
+    println!();
+    println!("Summary:");
+    println!(
+        "Domains: {}  Forests: {}  Hosts: {}  No domain: {}",
+        domains.len(),
+        forests.len(),
+        chs.len(),
+        no_domain.len()
+    );
+
+    for (name, domain) in domains.iter() {
+        println!();
+        match &domain.nb_domain {
+            Some(nb_domain) if !nb_domain.eq_ignore_ascii_case(name) => {
+                println!("Domain: {} ({})", name, nb_domain)
+            }
+            _ => println!("Domain: {}", name),
+        }
+        println!("Hosts: {}", domain.hosts);
+        if domain.forests.len() != 0 {
+            println!(
+                "Forest: {}",
+                domain
+                    .forests
+                    .iter()
+                    .map(|f| f.as_str())
+                    .collect::<Vec<&str>>()
+                    .join(" | ")
+            );
+        }
+        println!("Versions:");
+        for (version, count) in domain.versions.iter() {
+            println!("  {}: {}", version, count);
+        }
+    }
+
+    if no_domain.len() != 0 {
+        println!();
+        println!("No domain:");
+        for ch in no_domain {
+            match ch.challenge.nb_computer_name() {
+                Some(name) => println!("  {} ({})", ch.target, name),
+                None => println!("  {}", ch.target),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::challenge::ChallengeTarget;
+    use ntlm::{AvPair, ChallengeMsg};
+
+    fn challenge(av_pairs: Vec<AvPair>) -> Challenge {
+        let mut msg = ChallengeMsg::default();
+        msg.target_info = av_pairs.into();
+        return Challenge::new(ChallengeTarget::Url("http://ws/".into()), msg);
+    }
+
+    fn domain_host(nb_domain: &str, dns_domain: &str) -> Challenge {
+        return challenge(vec![
+            AvPair::NbDomainName(nb_domain.to_string()),
+            AvPair::NbComputerName("WS01".to_string()),
+            AvPair::DnsDomainName(dns_domain.to_string()),
+            AvPair::DnsTreeName(dns_domain.to_string()),
+            AvPair::EOL,
+        ]);
+    }
+
+    #[test]
+    fn group_hosts_by_domain() {
+        let chs = vec![
+            domain_host("CONTOSO", "contoso.local"),
+            domain_host("contoso", "CONTOSO.LOCAL"),
+            domain_host("fabrikam", ""),
+        ];
+        let Domains { domains, no_domain } = Domains::new(&chs);
+
+        assert_eq!(no_domain.len(), 0);
+        assert_eq!(
+            domains.keys().collect::<Vec<&String>>(),
+            vec!["FABRIKAM", "contoso.local"]
+        );
+
+        let contoso = &domains["contoso.local"];
+        assert_eq!(contoso.nb_domain.as_deref(), Some("CONTOSO"));
+        assert_eq!(contoso.hosts, 2);
+        assert_eq!(
+            contoso.forests.iter().collect::<Vec<&String>>(),
+            vec!["contoso.local"]
+        );
+        assert_eq!(contoso.versions.get("Unknown"), Some(&2));
+    }
+
+    #[test]
+    fn group_hosts_without_domain() {
+        let chs = vec![
+            // workgroup hosts report their computer name as domain
+            challenge(vec![
+                AvPair::NbDomainName("ws02".to_string()),
+                AvPair::NbComputerName("WS02".to_string()),
+                AvPair::EOL,
+            ]),
+            challenge(vec![
+                AvPair::NbDomainName("".to_string()),
+                AvPair::NbComputerName("WS03".to_string()),
+                AvPair::EOL,
+            ]),
+            challenge(vec![AvPair::EOL]),
+            domain_host("CONTOSO", "contoso.local"),
+        ];
+        let Domains { domains, no_domain } = Domains::new(&chs);
+
+        assert_eq!(domains.len(), 1);
+        assert_eq!(domains["contoso.local"].hosts, 1);
+        assert_eq!(no_domain.len(), 3);
+        assert_eq!(
+            no_domain
+                .iter()
+                .map(|ch| ch.challenge.nb_computer_name().cloned())
+                .collect::<Vec<Option<String>>>(),
+            vec![Some("WS02".to_string()), Some("WS03".to_string()), None]
+        );
+    }
+}
@@ -0,0 +1,175 @@
+use super::field_to_string;
+use serde_json::{Map, Value};
+
+enum Part {
+    Text(String),
+    Field(String),
+}
+
+/// Template to print each challenge in a custom format, where the fields
+/// are indicated by name between braces, like `{ip}\t{dns_computer}`.
+/// Literal braces are written as `{{` and `}}`, and the escapes
+/// `\t`, `\n` and `\\` are supported.
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+impl Template {
+    pub fn parse(template: &str) -> Result<Self, String> {
+        let mut parts = Vec::new();
+        let mut text = String::new();
+        let mut chars = template.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => match chars.next() {
+                    Some('t') => text.push('\t'),
+                    Some('n') => text.push('\n'),
+                    Some('\\') => text.push('\\'),
+                    Some(c) => {
+                        text.push('\\');
+                        text.push(c);
+                    }
+                    None => text.push('\\'),
+                },
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    text.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    text.push('}');
+                }
+                '{' => {
+                    let mut name = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => name.push(c),
+                            None => {
+                                return Err(format!(
+                                    "Unclosed field '{{{}' in format",
+                                    name
+                                ))
+                            }
+                        }
+                    }
+
+                    if text.len() != 0 {
+                        parts.push(Part::Text(text));
+                        text = String::new();
+                    }
+                    parts.push(Part::Field(name.trim().to_string()));
+                }
+                '}' => {
+                    return Err(format!("Unexpected '}}' in format"));
+                }
+                c => text.push(c),
+            }
+        }
+
+        if text.len() != 0 {
+            parts.push(Part::Text(text));
+        }
+
+        return Ok(Self { parts });
+    }
+
+    /// Returns the names of the fields used in the template.
+    pub fn fields(&self) -> Vec<String> {
+        return self
+            .parts
+            .iter()
+            .filter_map(|p| match p {
+                Part::Field(name) => Some(name.clone()),
+                Part::Text(_) => None,
+            })
+            .collect();
+    }
+
+    pub fn render(&self, fields: &Map<String, Value>) -> String {
+        let mut msg = String::new();
+        for part in self.parts.iter() {
+            match part {
+                Part::Text(text) => msg.push_str(text),
+                Part::Field(name) => msg.push_str(&field_to_string(
+                    fields.get(name).unwrap_or(&Value::Null),
+                )),
+            }
+        }
+
+        return msg;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::challenge::{Challenge, ChallengeTarget, Host};
+    use crate::printer::{challenge_fields, check_fields};
+    use ntlm::ChallengeMsg;
+
+    fn fields() -> Map<String, Value> {
+        let mut fields = Map::new();
+        fields.insert("ip".to_string(), Value::from("10.0.0.1"));
+        fields.insert("dns_computer".to_string(), Value::from("ws01"));
+        return fields;
+    }
+
+    #[test]
+    fn render_fields_and_escapes() {
+        let template =
+            Template::parse("{ip}\\t{ dns_computer }\\n\\\\ \\d").unwrap();
+        assert_eq!(template.fields(), vec!["ip", "dns_computer"]);
+        assert_eq!(template.render(&fields()), "10.0.0.1\tws01\n\\ \\d");
+    }
+
+    #[test]
+    fn render_escaped_braces() {
+        let template = Template::parse("{{{ip}}} {{}}").unwrap();
+        assert_eq!(template.fields(), vec!["ip"]);
+        assert_eq!(template.render(&fields()), "{10.0.0.1} {}");
+    }
+
+    #[test]
+    fn reject_unclosed_and_unexpected_braces() {
+        assert_eq!(
+            Template::parse("{ip}\\t{dns_computer").err(),
+            Some("Unclosed field '{dns_computer' in format".to_string())
+        );
+        assert_eq!(
+            Template::parse("ip}").err(),
+            Some("Unexpected '}' in format".to_string())
+        );
+    }
+
+    #[test]
+    fn reject_unknown_fields() {
+        let template = Template::parse("{ip} {computer}").unwrap();
+        assert_eq!(template.render(&fields()), "10.0.0.1 ");
+
+        let err = check_fields(&template.fields()).unwrap_err();
+        assert!(err.starts_with("Unknown field 'computer', valid fields"));
+        assert!(
+            check_fields(&Template::parse("{ip}").unwrap().fields()).is_ok()
+        );
+    }
+
+    #[test]
+    fn render_merged_hostnames() {
+        let host = Host::new(
+            "10.0.0.1".parse().unwrap(),
+            vec!["srv01".to_string(), "srv01.contoso.local".to_string()],
+        );
+        let ch = Challenge::new(
+            ChallengeTarget::Host(host),
+            ChallengeMsg::default(),
+        );
+
+        let template = Template::parse("{ip}\\t{hostnames}").unwrap();
+        assert_eq!(
+            template.render(&challenge_fields(&ch)),
+            "10.0.0.1\tsrv01 | srv01.contoso.local"
+        );
+    }
+}
@@ -0,0 +1,483 @@
+use ipnet::IpNet;
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+pub type IpIter = Box<dyn Iterator<Item = IpAddr> + Send>;
+
+/// Target that specifies several IPs, in any of the forms:
+/// - CIDR: `10.0.0.0/24`
+/// - Dash range: `10.0.0.10-200` or `10.0.0.10-10.0.3.5`
+/// - Octet ranges and wildcards: `10.0-3.*.1` or `10.0.0.1,5,7`
+/// - Comma list of the previous ones: `10.0.0.1,10.0.1.0/24`
+#[derive(Clone)]
+pub enum IpRange {
+    Ip(IpAddr),
+    Net(IpNet),
+    Span(IpAddr, IpAddr),
+    Octets(Vec<Vec<u8>>),
+    List(Vec<IpRange>),
+}
+
+impl IpRange {
+    /// Returns None if the target is not an IP range, like a hostname.
+    pub fn parse(target: &str) -> Option<Self> {
+        if let Ok(net) = target.parse::<IpNet>() {
+            return Some(Self::Net(net));
+        }
+
+        if let Ok(ip) = target.parse::<IpAddr>() {
+            return Some(Self::Ip(ip));
+        }
+
+        if let Some(range) = parse_dash_range(target) {
+            return Some(range);
+        }
+
+        if let Some(range) = parse_octets(target) {
+            return Some(range);
+        }
+
+        return parse_list(target);
+    }
+
+    pub fn contains(&self, ip: &IpAddr) -> bool {
+        match (self, ip) {
+            (Self::Ip(range_ip), _) => range_ip == ip,
+            (Self::Net(net), _) => net.contains(ip),
+            (
+                Self::Span(IpAddr::V4(start), IpAddr::V4(end)),
+                IpAddr::V4(ip),
+            ) => start <= ip && ip <= end,
+            (
+                Self::Span(IpAddr::V6(start), IpAddr::V6(end)),
+                IpAddr::V6(ip),
+            ) => start <= ip && ip <= end,
+            (Self::Octets(octets), IpAddr::V4(ip)) => octets
+                .iter()
+                .zip(ip.octets().iter())
+                .all(|(values, o)| values.contains(o)),
+            (Self::List(ranges), _) => ranges.iter().any(|r| r.contains(ip)),
+            _ => false,
+        }
+    }
+
+    /// Returns the IPs of the range, which are generated when requested,
+    /// so big ranges don't use memory.
+    pub fn ips(self) -> IpIter {
+        match self {
+            Self::Ip(ip) => Box::new(std::iter::once(ip)),
+            Self::Net(net) => Box::new(net.hosts()),
+            Self::Span(IpAddr::V4(start), IpAddr::V4(end)) => Box::new(
+                (u32::from(start)..=u32::from(end))
+                    .map(|ip| IpAddr::V4(Ipv4Addr::from(ip))),
+            ),
+            Self::Span(IpAddr::V6(start), IpAddr::V6(end)) => Box::new(
+                (u128::from(start)..=u128::from(end))
+                    .map(|ip| IpAddr::V6(Ipv6Addr::from(ip))),
+            ),
+            Self::Span(_, _) => Box::new(std::iter::empty()),
+            Self::Octets(octets) => {
+                let total: u64 =
+                    octets.iter().map(|o| o.len() as u64).product();
+                Box::new((0..total).map(move |mut i| {
+                    let mut ip = [0; 4];
+                    for (j, values) in octets.iter().enumerate().rev() {
+                        let n = values.len() as u64;
+                        ip[j] = values[(i % n) as usize];
+                        i /= n;
+                    }
+                    IpAddr::V4(Ipv4Addr::from(ip))
+                }))
+            }
+            Self::List(ranges) => {
+                Box::new(ranges.into_iter().flat_map(|r| r.ips()))
+            }
+        }
+    }
+}
+
+/// Parses ranges like `10.0.0.10-200`, `10.0.0.10-10.0.3.5` or
+/// `fe80::1-fe80::ff`.
+fn parse_dash_range(target: &str) -> Option<IpRange> {
+    let (start, end) = target.split_once('-')?;
+    let start = start.trim().parse::<IpAddr>().ok()?;
+    let end = end.trim();
+
+    let end = match start {
+        IpAddr::V4(start) => match end.parse::<Ipv4Addr>() {
+            Ok(end) => IpAddr::V4(end),
+            Err(_) => {
+                // only the last octet is specified
+                let last = end.parse::<u8>().ok()?;
+                let o = start.octets();
+                IpAddr::V4(Ipv4Addr::new(o[0], o[1], o[2], last))
+            }
+        },
+        IpAddr::V6(_) => IpAddr::V6(end.parse::<Ipv6Addr>().ok()?),
+    };
+
+    if start > end {
+        return None;
+    }
+
+    return Some(IpRange::Span(start, end));
+}
+
+/// Parses IPv4 addresses where each octet can be a number, a range, a
+/// wildcard or a comma list of them, like `10.0-3.*.1,5`.
+fn parse_octets(target: &str) -> Option<IpRange> {
+    let parts: Vec<&str> = target.split('.').collect();
+    if parts.len() != 4 {
+        return None;
+    }
+
+    let mut octets = Vec::new();
+    for part in parts {
+        octets.push(parse_octet(part)?);
+    }
+
+    return Some(IpRange::Octets(octets));
+}
+
+fn parse_octet(part: &str) -> Option<Vec<u8>> {
+    if part == "*" {
+        return Some((0..=255).collect());
+    }
+
+    let mut values = Vec::new();
+    for item in part.split(',') {
+        match item.split_once('-') {
+            Some((start, end)) => {
+                let start: u8 = start.parse().ok()?;
+                let end: u8 = end.parse().ok()?;
+                if start > end {
+                    return None;
+                }
+                values.extend(start..=end);
+            }
+            None => values.push(item.parse().ok()?),
+        }
+    }
+
+    values.sort();
+    values.dedup();
+    return Some(values);
+}
+
+/// Parses a comma list of ranges, like `10.0.0.1,10.0.1.0/24`.
+fn parse_list(target: &str) -> Option<IpRange> {
+    if !target.contains(',') {
+        return None;
+    }
+
+    let mut ranges = Vec::new();
+    for item in target.split(',') {
+        let item = item.trim();
+        if item.len() == 0 {
+            continue;
+        }
+        ranges.push(IpRange::parse(item)?);
+    }
+
+    return Some(IpRange::List(ranges));
+}
+
+/// Hosts that must not be scanned, given as IP ranges or hostnames.
+pub struct Exclusions {
+    ranges: Vec<IpRange>,
+    names: HashSet<String>,
+}
+
+impl Exclusions {
+    /// Parses the excluded hosts given inline and in files, one per line.
+    pub fn new(
+        hosts: &Vec<String>,
+        files: &Vec<String>,
+    ) -> Result<Self, String> {
+        let mut exclusions = Self {
+            ranges: Vec::new(),
+            names: HashSet::new(),
+        };
+
+        for host in hosts {
+            exclusions.add(host);
+        }
+
+        for path in files {
+            let file = File::open(path)
+                .map_err(|e| format!("Error opening '{}': {}", path, e))?;
+            for line in BufReader::new(file).lines() {
+                let line = line
+                    .map_err(|e| format!("Error reading '{}': {}", path, e))?;
+                let line = line.trim();
+                if line.len() != 0 && !line.starts_with("#") {
+                    exclusions.add(line);
+                }
+            }
+        }
+
+        return Ok(exclusions);
+    }
+
+    fn add(&mut self, host: &str) {
+        let (ranges, names) = parse_ranges_and_names(host);
+        self.ranges.extend(ranges);
+        for name in names {
+            self.names.insert(normalize_name(&name));
+        }
+    }
+
+    pub fn contains_ip(&self, ip: &IpAddr) -> bool {
+        return self.ranges.iter().any(|r| r.contains(ip));
+    }
+
+    /// Checks if the host, given as IP or hostname, is excluded.
+    pub fn contains_host(&self, host: &str) -> bool {
+        match host
+            .trim_matches(|c| c == '[' || c == ']')
+            .parse::<IpAddr>()
+        {
+            Ok(ip) => self.contains_ip(&ip),
+            Err(_) => self.names.contains(&normalize_name(host)),
+        }
+    }
+}
+
+/// Hosts that can be scanned when following discovered names, given as IP
+/// ranges or domain suffixes.
+pub struct Scope {
+    ranges: Vec<IpRange>,
+    suffixes: Vec<String>,
+}
+
+impl Scope {
+    pub fn new(items: &[String]) -> Self {
+        let mut scope = Self {
+            ranges: Vec::new(),
+            suffixes: Vec::new(),
+        };
+
+        for item in items {
+            let (ranges, names) = parse_ranges_and_names(item);
+            scope.ranges.extend(ranges);
+            for name in names {
+                let suffix = normalize_name(&name);
+                let suffix = suffix.trim_start_matches("*.");
+                scope.suffixes.push(suffix.trim_matches('.').to_string());
+            }
+        }
+
+        return scope;
+    }
+
+    pub fn has_ranges(&self) -> bool {
+        return self.ranges.len() != 0;
+    }
+
+    pub fn contains_ip(&self, ip: &IpAddr) -> bool {
+        return self.ranges.iter().any(|r| r.contains(ip));
+    }
+
+    /// Returns the IPs of the host that can be scanned: all of them if its
+    /// name is in scope, or only the ones in the ranges otherwise.
+    pub fn filter_ips(&self, name: &str, ips: Vec<IpAddr>) -> Vec<IpAddr> {
+        if self.contains_name(name) {
+            return ips;
+        }
+        return ips.into_iter().filter(|ip| self.contains_ip(ip)).collect();
+    }
+
+    /// Checks if the name is one of the domain suffixes or a subdomain.
+    pub fn contains_name(&self, name: &str) -> bool {
+        let name = normalize_name(name);
+        return self
+            .suffixes
+            .iter()
+            .any(|s| name == *s || name.ends_with(&format!(".{}", s)));
+    }
+}
+
+/// Parses a comma list where ranges and names can be mixed. The whole item
+/// is parsed first, since some ranges contain commas, like `10.0.0.1,5,7`.
+fn parse_ranges_and_names(item: &str) -> (Vec<IpRange>, Vec<String>) {
+    if let Some(range) = IpRange::parse(item) {
+        return (vec![range], Vec::new());
+    }
+
+    let mut ranges = Vec::new();
+    let mut names = Vec::new();
+    for part in item.split(',') {
+        let part = part.trim();
+        if part.len() == 0 {
+            continue;
+        }
+
+        match IpRange::parse(part) {
+            Some(range) => ranges.push(range),
+            None => names.push(part.to_string()),
+        }
+    }
+
+    return (ranges, names);
+}
+
+fn normalize_name(name: &str) -> String {
+    return name.trim().trim_end_matches('.').to_lowercase();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ips(range: &str) -> Vec<String> {
+        let range = IpRange::parse(range).unwrap();
+        return range.ips().map(|ip| ip.to_string()).collect();
+    }
+
+    #[test]
+    fn parse_ranges() {
+        assert_eq!(ips("10.0.0.1"), vec!["10.0.0.1"]);
+        assert_eq!(ips("10.0.0.0/30"), vec!["10.0.0.1", "10.0.0.2"]);
+        assert_eq!(ips("10.0.0.1/32"), vec!["10.0.0.1"]);
+        assert_eq!(ips("fe80::/127"), vec!["fe80::", "fe80::1"]);
+        assert_eq!(ips("10.0.0.254-255"), vec!["10.0.0.254", "10.0.0.255"]);
+        assert_eq!(
+            ips("10.0.0.255-10.0.1.1"),
+            vec!["10.0.0.255", "10.0.1.0", "10.0.1.1"]
+        );
+        assert_eq!(ips("fe80::1-fe80::2"), vec!["fe80::1", "fe80::2"]);
+        assert_eq!(
+            ips("10.0-1.*.1").len(),
+            2 * 256,
+            "octet ranges and wildcards"
+        );
+        assert_eq!(
+            ips("10.0-1.2.3,5"),
+            vec!["10.0.2.3", "10.0.2.5", "10.1.2.3", "10.1.2.5"]
+        );
+        assert_eq!(
+            ips("10.0.0.1,5,7"),
+            vec!["10.0.0.1", "10.0.0.5", "10.0.0.7"]
+        );
+        assert_eq!(
+            ips("10.0.0.1, 10.0.1.0/31,fe80::1"),
+            vec!["10.0.0.1", "10.0.1.0", "10.0.1.1", "fe80::1"]
+        );
+    }
+
+    #[test]
+    fn parse_invalid_ranges() {
+        for target in &[
+            "10.0.0.200-10",
+            "10.0.0.5-10.0.0.1",
+            "fe80::2-fe80::1",
+            "10.0.0.1-fe80::1",
+            "10.0.5-1.1",
+            "10.0.0.256",
+            "10.0.0.1-300",
+            "10.0.0",
+            "10.0.0.0/33",
+            "ws01.contoso.local",
+            "10.0.0.1,ws01",
+        ] {
+            assert!(IpRange::parse(target).is_none(), "{}", target);
+        }
+    }
+
+    #[test]
+    fn contains_same_ips() {
+        let outside: Vec<IpAddr> = vec![
+            "9.255.255.255".parse().unwrap(),
+            "10.0.2.0".parse().unwrap(),
+            "10.2.0.1".parse().unwrap(),
+            "fe80::3".parse().unwrap(),
+        ];
+
+        for target in &[
+            "10.0.0.1",
+            "10.0.0.0/28",
+            "10.0.0.250-10.0.1.3",
+            "fe80::1-fe80::2",
+            "10.0-1.0-1.1,3",
+            "10.0.0.1,10.0.1.0/30,fe80::1",
+        ] {
+            let range = IpRange::parse(target).unwrap();
+            for ip in range.clone().ips() {
+                assert!(range.contains(&ip), "{} in {}", ip, target);
+            }
+            for ip in outside.iter() {
+                assert!(!range.contains(ip), "{} in {}", ip, target);
+            }
+        }
+    }
+
+    #[test]
+    fn exclude_ranges_and_names() {
+        let path = std::env::temp_dir()
+            .join(format!("ntlm-info-exclude-{}", std::process::id()));
+        std::fs::write(&path, "# comment\n\n10.0.1.0/24\nws02\n").unwrap();
+
+        let exclusions = Exclusions::new(
+            &vec![
+                "10.0.0.1,5".to_string(),
+                "WS01.Contoso.Local.".to_string(),
+                "srv01,srv02".to_string(),
+                "fe80::1".to_string(),
+            ],
+            &vec![path.to_str().unwrap().to_string()],
+        );
+        std::fs::remove_file(&path).unwrap();
+        let exclusions = exclusions.unwrap();
+
+        assert!(exclusions.contains_host("10.0.0.5"));
+        assert!(!exclusions.contains_host("10.0.0.2"));
+        assert!(exclusions.contains_host("10.0.1.7"));
+        assert!(exclusions.contains_host("[fe80::1]"));
+        assert!(exclusions.contains_host("ws01.contoso.local"));
+        assert!(!exclusions.contains_host("ws01"));
+        assert!(exclusions.contains_host("WS02"));
+        assert!(exclusions.contains_host("srv02"));
+        assert!(!exclusions.contains_host("comment"));
+        assert!(exclusions.contains_ip(&"10.0.1.255".parse().unwrap()));
+
+        // ranges and names mixed in the same list
+        let exclusions = Exclusions::new(
+            &vec!["10.0.2.0/24,ws03,10.0.3.1".to_string()],
+            &vec![],
+        )
+        .unwrap();
+        assert!(exclusions.contains_host("10.0.2.9"));
+        assert!(exclusions.contains_host("10.0.3.1"));
+        assert!(exclusions.contains_host("ws03"));
+        assert!(!exclusions.contains_host("10.0.2.0/24"));
+
+        let missing = vec!["/nonexistent/exclusions".to_string()];
+        assert!(Exclusions::new(&vec![], &missing).is_err());
+    }
+
+    #[test]
+    fn parse_scope() {
+        let scope = Scope::new(&[
+            "10.0.0.1,5,7".to_string(),
+            "10.1.0.0/16,*.Contoso.local.,fabrikam.com".to_string(),
+        ]);
+
+        for ip in &["10.0.0.1", "10.0.0.5", "10.0.0.7", "10.1.2.3"] {
+            assert!(scope.contains_ip(&ip.parse().unwrap()), "{}", ip);
+        }
+        assert!(!scope.contains_ip(&"10.0.0.2".parse().unwrap()));
+
+        assert!(scope.contains_name("contoso.local"));
+        assert!(scope.contains_name("DC01.contoso.local."));
+        assert!(scope.contains_name("www.fabrikam.com"));
+        assert!(!scope.contains_name("notcontoso.local"));
+        assert!(!scope.contains_name("5"));
+
+        let ips: Vec<IpAddr> =
+            vec!["10.1.0.1".parse().unwrap(), "192.168.0.1".parse().unwrap()];
+        assert_eq!(scope.filter_ips("www.contoso.local", ips.clone()), ips);
+        assert_eq!(scope.filter_ips("www.other.local", ips.clone()), &ips[..1]);
+    }
+}
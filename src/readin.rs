@@ -1,20 +1,48 @@
-use log::warn;
+mod scan;
+
+use log::{info, warn};
+pub use scan::ScanHost;
+use scan::ScanReader;
+use std::collections::VecDeque;
 use std::fs::File;
 use std::io::Lines;
 use std::io::{self, BufRead, BufReader, Stdin};
 
+/// Function to select the targets of a command from the hosts found by a
+/// port scanner.
+pub type ScanTargets = fn(&ScanHost) -> Vec<String>;
+
 /// Function to read the inputs from files, strings or stdin
 /// in a normalized iterator of strings.
 pub fn read_inputs(
     inputs: Vec<String>,
     use_stdin: bool,
     filter_blanks: bool,
+) -> impl Iterator<Item = String> {
+    return read_inputs_with_scans(inputs, use_stdin, filter_blanks, None);
+}
+
+/// Same as `read_inputs`, but files with results of port scanners (nmap
+/// XML, grepable nmap, masscan JSON or list) are also accepted, and the
+/// targets are retrieved from their hosts with `scan_targets`.
+pub fn read_targets(
+    inputs: Vec<String>,
+    scan_targets: ScanTargets,
+) -> impl Iterator<Item = String> {
+    return read_inputs_with_scans(inputs, true, true, Some(scan_targets));
+}
+
+fn read_inputs_with_scans(
+    inputs: Vec<String>,
+    use_stdin: bool,
+    filter_blanks: bool,
+    scan_targets: Option<ScanTargets>,
 ) -> impl Iterator<Item = String> {
     let mut input_iter: Box<dyn Iterator<Item = String>>;
     input_iter = if inputs.len() == 0 && use_stdin {
         Box::new(StdinIter::new())
     } else {
-        Box::new(FileStringIter::new(inputs))
+        Box::new(FileStringIter::new(inputs, scan_targets))
     };
 
     input_iter = Box::new(input_iter.map(|s| s.trim().to_string()));
@@ -71,17 +99,95 @@ pub struct FileStringIter {
     items: Vec<String>,
     lines: Option<Lines<BufReader<File>>>,
     current_path: String,
+    scan_targets: Option<ScanTargets>,
+
+    /// Port scanner file being read.
+    scan: Option<ScanFile>,
+
+    /// Targets retrieved from the last host of a port scanner file.
+    scan_items: VecDeque<String>,
+}
+
+/// Port scanner file, whose hosts are read as the targets are required.
+struct ScanFile {
+    path: String,
+    hosts: ScanReader<BufReader<File>>,
+    host_count: usize,
+    target_count: usize,
 }
 
 impl FileStringIter {
-    pub fn new(mut items: Vec<String>) -> Self {
+    pub fn new(
+        mut items: Vec<String>,
+        scan_targets: Option<ScanTargets>,
+    ) -> Self {
         items.reverse();
         return Self {
             items,
             lines: None,
             current_path: "".to_string(),
+            scan_targets,
+            scan: None,
+            scan_items: VecDeque::new(),
         };
     }
+
+    /// Starts to read the hosts of the file if it contains results of a
+    /// port scanner. Returns the reader otherwise.
+    fn read_scan(&mut self, path: &str, file: File) -> Option<BufReader<File>> {
+        let mut reader = BufReader::new(file);
+        if self.scan_targets.is_none() {
+            return Some(reader);
+        }
+
+        let format = match reader.fill_buf() {
+            Ok(head) => scan::detect_format(head),
+            Err(_) => None,
+        };
+        let format = match format {
+            Some(format) => format,
+            None => return Some(reader),
+        };
+
+        info!("Reading {:?} file {}", format, path);
+        self.scan = Some(ScanFile {
+            path: path.to_string(),
+            hosts: ScanReader::new(format, reader),
+            host_count: 0,
+            target_count: 0,
+        });
+
+        return None;
+    }
+
+    /// Reads the targets of the next host of the port scanner file. Returns
+    /// false once the file is finished.
+    fn next_scan_host(&mut self) -> bool {
+        let (scan, scan_targets) = match (&mut self.scan, self.scan_targets) {
+            (Some(scan), Some(scan_targets)) => (scan, scan_targets),
+            _ => return false,
+        };
+
+        match scan.hosts.next() {
+            Some(Ok(host)) => {
+                let targets = scan_targets(&host);
+                scan.host_count += 1;
+                scan.target_count += targets.len();
+                self.scan_items.extend(targets);
+            }
+            Some(Err(err)) => warn!("Error parsing {}: {}", scan.path, err),
+            None => {
+                info!(
+                    "Read {} targets from {} hosts in {}",
+                    scan.target_count, scan.host_count, scan.path
+                );
+                self.scan = None;
+                return false;
+            }
+        }
+
+        return true;
+    }
 }
 
 impl Iterator for FileStringIter {
@@ -89,6 +195,14 @@ impl Iterator for FileStringIter {
 
     fn next(&mut self) -> Option<String> {
         loop {
+            if let Some(item) = self.scan_items.pop_front() {
+                return Some(item);
+            }
+
+            if self.next_scan_host() {
+                continue;
+            }
+
             if let Some(lines) = &mut self.lines {
                 if let Some(line) = lines.next() {
                     match line {
@@ -114,8 +228,10 @@ impl Iterator for FileStringIter {
 
             match File::open(&item) {
                 Ok(file) => {
-                    self.current_path = item;
-                    self.lines = Some(BufReader::new(file).lines());
+                    if let Some(reader) = self.read_scan(&item, file) {
+                        self.current_path = item;
+                        self.lines = Some(reader.lines());
+                    }
                 }
                 Err(_) => {
                     return Some(item);
@@ -0,0 +1,716 @@
+use serde_json::Value;
+use std::collections::VecDeque;
+use std::io::BufRead;
+
+/// Formats of port scanner results that can be used as input.
+#[derive(Clone, Copy, Debug)]
+pub enum ScanFormat {
+    /// nmap -oX
+    NmapXml,
+
+    /// masscan -oX, like nmap -oX but with a host element per port
+    MasscanXml,
+
+    /// nmap -oG
+    Gnmap,
+
+    /// masscan -oJ
+    MasscanJson,
+
+    /// masscan -oL
+    MasscanList,
+}
+
+/// Open TCP port found by a port scanner.
+pub struct ScanPort {
+    pub port: u16,
+    pub service: Option<String>,
+    pub tls: bool,
+}
+
+/// Host with open ports found by a port scanner.
+pub struct ScanHost {
+    pub addr: String,
+    pub ports: Vec<ScanPort>,
+}
+
+impl ScanHost {
+    /// Returns the address and port as `addr:port`, with IPv6 addresses
+    /// between brackets.
+    pub fn addr_port(&self, port: u16) -> String {
+        if self.addr.contains(':') {
+            return format!("[{}]:{}", self.addr, port);
+        }
+        return format!("{}:{}", self.addr, port);
+    }
+}
+
+/// Identifies the format of a scan results file by its first bytes.
+pub fn detect_format(head: &[u8]) -> Option<ScanFormat> {
+    let head = String::from_utf8_lossy(head);
+    let head = head.trim_start();
+
+    if head.starts_with("<?xml") || head.starts_with("<nmaprun") {
+        if head.contains("<nmaprun") {
+            if head.contains("scanner=\"masscan\"") {
+                return Some(ScanFormat::MasscanXml);
+            }
+            return Some(ScanFormat::NmapXml);
+        }
+        return None;
+    }
+
+    if head.starts_with("#masscan") {
+        return Some(ScanFormat::MasscanList);
+    }
+
+    if (head.starts_with('[') || head.starts_with('{'))
+        && head.contains("\"ports\"")
+    {
+        return Some(ScanFormat::MasscanJson);
+    }
+
+    if head.starts_with("# Nmap")
+        && head.lines().any(|l| l.starts_with("Host: "))
+    {
+        return Some(ScanFormat::Gnmap);
+    }
+
+    return None;
+}
+
+/// Reader of the hosts of a port scanner file, which is parsed as it is
+/// read, so only the host being parsed is kept in memory. In the nmap
+/// formats each host is complete in one element or line, so it is returned
+/// once parsed, whereas masscan writes a record per port, so the last host
+/// is kept until a record of other host is read, to merge the ports of
+/// consecutive records.
+pub struct ScanReader<R> {
+    format: ScanFormat,
+    reader: R,
+    line: Vec<u8>,
+
+    /// Text of the XML host element or JSON record being read.
+    buffer: String,
+    json: JsonSplitter,
+
+    hosts: ScanHosts,
+    pending: VecDeque<Result<ScanHost, String>>,
+    done: bool,
+}
+
+impl<R: BufRead> ScanReader<R> {
+    pub fn new(format: ScanFormat, reader: R) -> Self {
+        return Self {
+            format,
+            reader,
+            line: Vec::new(),
+            buffer: String::new(),
+            json: JsonSplitter::default(),
+            hosts: ScanHosts::new(),
+            pending: VecDeque::new(),
+            done: false,
+        };
+    }
+
+    fn parse_line(&mut self, line: &str) {
+        match self.format {
+            ScanFormat::NmapXml | ScanFormat::MasscanXml => {
+                self.buffer.push_str(line);
+                while let Some(element) = next_xml_host(&mut self.buffer) {
+                    if let Err(err) = parse_nmap_host(&element, &mut self.hosts)
+                    {
+                        self.pending.push_back(Err(err));
+                    }
+                }
+            }
+            ScanFormat::Gnmap => parse_gnmap_line(line, &mut self.hosts),
+            ScanFormat::MasscanJson => {
+                for c in line.chars() {
+                    if !self.json.push(c, &mut self.buffer) {
+                        continue;
+                    }
+
+                    let record = std::mem::take(&mut self.buffer);
+                    if let Err(err) =
+                        parse_masscan_record(&record, &mut self.hosts)
+                    {
+                        self.pending.push_back(Err(err));
+                    }
+                }
+            }
+            ScanFormat::MasscanList => {
+                parse_masscan_line(line, &mut self.hosts)
+            }
+        }
+
+        let complete =
+            matches!(self.format, ScanFormat::NmapXml | ScanFormat::Gnmap);
+        self.pending
+            .extend(self.hosts.take(complete).into_iter().map(Ok));
+    }
+}
+
+impl<R: BufRead> Iterator for ScanReader<R> {
+    type Item = Result<ScanHost, String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(host) = self.pending.pop_front() {
+                return Some(host);
+            }
+
+            if self.done {
+                return None;
+            }
+
+            self.line.clear();
+            match self.reader.read_until(b'\n', &mut self.line) {
+                Ok(0) => {
+                    self.done = true;
+                    self.pending
+                        .extend(self.hosts.take(true).into_iter().map(Ok));
+                }
+                Ok(_) => {
+                    let line = String::from_utf8_lossy(&self.line).to_string();
+                    self.parse_line(&line);
+                }
+                Err(err) => {
+                    self.done = true;
+                    return Some(Err(format!("Error reading: {}", err)));
+                }
+            }
+        }
+    }
+}
+
+/// Hosts being parsed, in the order in which they were found. The ports
+/// of consecutive records of the same host are merged.
+struct ScanHosts {
+    hosts: Vec<ScanHost>,
+}
+
+impl ScanHosts {
+    fn new() -> Self {
+        return Self { hosts: Vec::new() };
+    }
+
+    fn add(&mut self, addr: &str, port: ScanPort) {
+        let host = match self.hosts.last_mut() {
+            Some(host) if host.addr == addr => host,
+            _ => {
+                self.hosts.push(ScanHost {
+                    addr: addr.to_string(),
+                    ports: Vec::new(),
+                });
+                self.hosts.last_mut().unwrap()
+            }
+        };
+
+        if !host.ports.iter().any(|p| p.port == port.port) {
+            host.ports.push(port);
+        }
+    }
+
+    /// Returns the hosts, except the last one if it is not complete, since
+    /// the next records could have more of its ports.
+    fn take(&mut self, complete: bool) -> Vec<ScanHost> {
+        let last = match complete {
+            true => None,
+            false => self.hosts.pop(),
+        };
+        let hosts = std::mem::take(&mut self.hosts);
+        self.hosts.extend(last);
+        return hosts;
+    }
+}
+
+/// Removes from the buffer the text of the first complete `<host>`
+/// element, discarding the text before it.
+fn next_xml_host(buffer: &mut String) -> Option<String> {
+    // <hostnames> and <hosthint> are not hosts
+    let start = buffer.match_indices("<host").map(|(i, _)| i).find(|i| {
+        matches!(
+            buffer[i + "<host".len()..].chars().next(),
+            Some('>') | Some(' ') | Some('\t') | Some('\r') | Some('\n')
+        )
+    });
+
+    let start = match start {
+        Some(start) => start,
+        None => {
+            // keep a tag that may be completed by the next line
+            let keep = buffer.rfind('<').unwrap_or(buffer.len());
+            buffer.drain(..keep);
+            return None;
+        }
+    };
+
+    let end = match buffer[start..].find("</host>") {
+        Some(end) => start + end + "</host>".len(),
+        None => {
+            buffer.drain(..start);
+            return None;
+        }
+    };
+
+    let element = buffer[start..end].to_string();
+    buffer.drain(..end);
+    return Some(element);
+}
+
+fn parse_nmap_host(element: &str, hosts: &mut ScanHosts) -> Result<(), String> {
+    let doc = roxmltree::Document::parse(element)
+        .map_err(|e| format!("Invalid XML: {}", e))?;
+    let host = doc.root_element();
+
+    let addr = host
+        .children()
+        .filter(|n| n.has_tag_name("address"))
+        .find(|n| {
+            matches!(n.attribute("addrtype"), Some("ipv4") | Some("ipv6"))
+        })
+        .and_then(|n| n.attribute("addr"));
+
+    let addr = match addr {
+        Some(addr) => addr,
+        None => return Ok(()),
+    };
+
+    for port in host.descendants().filter(|n| n.has_tag_name("port")) {
+        if port.attribute("protocol") != Some("tcp") {
+            continue;
+        }
+
+        let open = port
+            .children()
+            .find(|n| n.has_tag_name("state"))
+            .and_then(|n| n.attribute("state"))
+            == Some("open");
+        if !open {
+            continue;
+        }
+
+        let number = match port.attribute("portid").map(|p| p.parse()) {
+            Some(Ok(number)) => number,
+            _ => continue,
+        };
+
+        let service = port.children().find(|n| n.has_tag_name("service"));
+        let name = service.and_then(|s| s.attribute("name"));
+        let tls = service.and_then(|s| s.attribute("tunnel")) == Some("ssl")
+            || name == Some("https");
+
+        hosts.add(
+            addr,
+            ScanPort {
+                port: number,
+                service: name.map(|s| s.to_string()),
+                tls,
+            },
+        );
+    }
+
+    return Ok(());
+}
+
+/// Parses lines like:
+/// `Host: 10.0.0.1 (ws01)  Ports: 445/open/tcp//microsoft-ds///, 80/...`
+fn parse_gnmap_line(line: &str, hosts: &mut ScanHosts) {
+    if !line.starts_with("Host: ") {
+        return;
+    }
+
+    let addr = match line["Host: ".len()..].split_whitespace().next() {
+        Some(addr) => addr,
+        None => return,
+    };
+
+    let ports = match line.split('\t').find(|f| f.starts_with("Ports: ")) {
+        Some(ports) => &ports["Ports: ".len()..],
+        None => return,
+    };
+
+    for port in ports.split(", ") {
+        let fields: Vec<&str> = port.trim().split('/').collect();
+        if fields.len() < 5 || fields[1] != "open" || fields[2] != "tcp" {
+            continue;
+        }
+
+        let number = match fields[0].parse() {
+            Ok(number) => number,
+            Err(_) => continue,
+        };
+
+        // services over TLS are shown as ssl|http
+        let (tls, service) = match fields[4].strip_prefix("ssl|") {
+            Some(service) => (true, service),
+            None => (fields[4] == "https", fields[4]),
+        };
+
+        hosts.add(
+            addr,
+            ScanPort {
+                port: number,
+                service: match service.len() {
+                    0 => None,
+                    _ => Some(service.to_string()),
+                },
+                tls,
+            },
+        );
+    }
+}
+
+/// Splits the objects of the masscan JSON output, which in some versions
+/// is not a valid JSON document (trailing commas), so each object is
+/// parsed independently, wherever the lines are broken.
+#[derive(Default)]
+struct JsonSplitter {
+    depth: usize,
+    in_string: bool,
+    escaped: bool,
+}
+
+impl JsonSplitter {
+    /// Adds the character to the buffer if it is part of an object.
+    /// Returns true when the object is complete.
+    fn push(&mut self, c: char, buffer: &mut String) -> bool {
+        if self.depth == 0 {
+            if c == '{' {
+                self.depth = 1;
+                buffer.push(c);
+            }
+            return false;
+        }
+
+        buffer.push(c);
+        if self.in_string {
+            if self.escaped {
+                self.escaped = false;
+            } else if c == '\\' {
+                self.escaped = true;
+            } else if c == '"' {
+                self.in_string = false;
+            }
+            return false;
+        }
+
+        match c {
+            '"' => self.in_string = true,
+            '{' => self.depth += 1,
+            '}' => {
+                self.depth -= 1;
+                return self.depth == 0;
+            }
+            _ => {}
+        }
+        return false;
+    }
+}
+
+fn parse_masscan_record(
+    record: &str,
+    hosts: &mut ScanHosts,
+) -> Result<(), String> {
+    let record: Value = serde_json::from_str(record)
+        .map_err(|e| format!("Invalid masscan JSON: {}", e))?;
+
+    let addr = match record.get("ip").and_then(|ip| ip.as_str()) {
+        Some(addr) => addr,
+        None => return Ok(()),
+    };
+
+    let ports = match record.get("ports").and_then(|p| p.as_array()) {
+        Some(ports) => ports,
+        None => return Ok(()),
+    };
+
+    for port in ports {
+        if port.get("proto").and_then(|p| p.as_str()) != Some("tcp")
+            || port.get("status").and_then(|s| s.as_str()) != Some("open")
+        {
+            continue;
+        }
+
+        if let Some(number) = port.get("port").and_then(|p| p.as_u64()) {
+            hosts.add(
+                addr,
+                ScanPort {
+                    port: number as u16,
+                    service: None,
+                    tls: false,
+                },
+            );
+        }
+    }
+
+    return Ok(());
+}
+
+/// Parses lines like `open tcp 445 10.0.0.1 1612345678`.
+fn parse_masscan_line(line: &str, hosts: &mut ScanHosts) {
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    if fields.len() < 4 || fields[0] != "open" || fields[1] != "tcp" {
+        return;
+    }
+
+    if let Ok(number) = fields[2].parse() {
+        hosts.add(
+            fields[3],
+            ScanPort {
+                port: number,
+                service: None,
+                tls: false,
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Reads the hosts of a scan as `addr:port,port`.
+    fn read_hosts(format: ScanFormat, content: &str) -> Vec<String> {
+        return ScanReader::new(format, content.as_bytes())
+            .map(|host| {
+                let host = host.unwrap();
+                let ports: Vec<String> =
+                    host.ports.iter().map(|p| p.port.to_string()).collect();
+                format!("{}:{}", host.addr, ports.join(","))
+            })
+            .collect();
+    }
+
+    #[test]
+    fn stream_nmap_xml_hosts() {
+        let content = r#"<?xml version="1.0"?>
+<nmaprun scanner="nmap">
+<hosthint><status state="up"/><address addr="10.0.0.9" addrtype="ipv4"/></hosthint>
+<host><status state="up"/>
+<address addr="10.0.0.1" addrtype="ipv4"/><hostnames><hostname name="ws01"/></hostnames>
+<ports><port protocol="tcp" portid="445"><state state="open"/></port></ports>
+</host><host><address addr="10.0.0.2" addrtype="ipv4"/><ports><port protocol="tcp"
+portid="80"><state state="open"/><service name="http"/></port></ports></host>
+"#;
+        // an interrupted scan without </nmaprun> is still read
+        let mut reader =
+            ScanReader::new(ScanFormat::NmapXml, content.as_bytes());
+        let first = reader.next().unwrap().unwrap();
+        assert_eq!(first.addr, "10.0.0.1");
+        assert!(!reader.buffer.contains("10.0.0.1"));
+
+        assert_eq!(
+            read_hosts(ScanFormat::NmapXml, content),
+            vec!["10.0.0.1:445", "10.0.0.2:80"]
+        );
+    }
+
+    #[test]
+    fn merge_consecutive_masscan_ports() {
+        let content = "#masscan
+open tcp 445 10.0.0.1 1612345678
+open tcp 139 10.0.0.1 1612345678
+open tcp 80 10.0.0.2 1612345678
+open tcp 8080 10.0.0.1 1612345678
+# end
+";
+        // the hosts are returned before the end of the file
+        let mut reader =
+            ScanReader::new(ScanFormat::MasscanList, content.as_bytes());
+        let first = reader.next().unwrap().unwrap();
+        assert_eq!(first.addr, "10.0.0.1");
+        assert!(!reader.done);
+
+        assert_eq!(
+            read_hosts(ScanFormat::MasscanList, content),
+            vec!["10.0.0.1:445,139", "10.0.0.2:80", "10.0.0.1:8080"]
+        );
+    }
+
+    #[test]
+    fn skip_invalid_xml_hosts() {
+        let content = "<nmaprun>
+<host><address addr=\"10.0.0.1\" addrtype=\"ipv4\"></host>
+<host><address addr=\"10.0.0.2\" addrtype=\"ipv4\"/><ports><port protocol=\"tcp\" portid=\"445\"><state state=\"open\"/></port></ports></host>
+";
+        let hosts: Vec<Result<ScanHost, String>> =
+            ScanReader::new(ScanFormat::NmapXml, content.as_bytes()).collect();
+        assert_eq!(hosts.len(), 2);
+        assert!(hosts[0].is_err());
+        assert_eq!(hosts[1].as_ref().unwrap().addr, "10.0.0.2");
+    }
+
+    #[test]
+    fn detect_scan_formats() {
+        let detect = |head: &str| detect_format(head.as_bytes());
+        assert!(matches!(
+            detect("<?xml version=\"1.0\"?>\n<nmaprun scanner=\"nmap\">"),
+            Some(ScanFormat::NmapXml)
+        ));
+        assert!(matches!(
+            detect("<?xml version=\"1.0\"?>\n<nmaprun scanner=\"masscan\">"),
+            Some(ScanFormat::MasscanXml)
+        ));
+        assert!(matches!(
+            detect("# Nmap 7.80 scan initiated\nHost: 10.0.0.1 ()\tStatus: Up"),
+            Some(ScanFormat::Gnmap)
+        ));
+        assert!(matches!(
+            detect("[\n{ \"ip\": \"10.0.0.1\", \"ports\": [] },"),
+            Some(ScanFormat::MasscanJson)
+        ));
+        assert!(matches!(
+            detect("#masscan\nopen tcp 445 10.0.0.1 1"),
+            Some(ScanFormat::MasscanList)
+        ));
+
+        // other XML, JSON and text files are targets lists
+        assert!(detect("<?xml version=\"1.0\"?>\n<root/>").is_none());
+        assert!(detect("[\"10.0.0.1\"]").is_none());
+        assert!(detect("# Nmap targets\n10.0.0.1").is_none());
+        assert!(detect("10.0.0.1\nws01.contoso.local").is_none());
+    }
+
+    #[test]
+    fn parse_nmap_xml_ports() {
+        let content = r#"<?xml version="1.0"?>
+<nmaprun scanner="nmap">
+<host><address addr="10.0.0.1" addrtype="ipv4"/>
+<address addr="00:11:22:33:44:55" addrtype="mac"/>
+<ports>
+<port protocol="tcp" portid="445"><state state="open"/><service name="microsoft-ds"/></port>
+<port protocol="tcp" portid="139"><state state="closed"/></port>
+<port protocol="tcp" portid="80"><state state="filtered"/></port>
+<port protocol="udp" portid="137"><state state="open"/></port>
+<port protocol="tcp" portid="8443"><state state="open"/><service name="http" tunnel="ssl"/></port>
+</ports></host>
+<host><address addr="fe80::1" addrtype="ipv6"/>
+<ports><port protocol="tcp" portid="443"><state state="open"/><service name="https"/></port></ports>
+</host>
+<host><address addr="10.0.0.3" addrtype="ipv4"/>
+<ports><port protocol="tcp" portid="445"><state state="filtered"/></port></ports>
+</host>
+</nmaprun>
+"#;
+        let hosts: Vec<ScanHost> =
+            ScanReader::new(ScanFormat::NmapXml, content.as_bytes())
+                .map(|h| h.unwrap())
+                .collect();
+        assert_eq!(hosts.len(), 2);
+
+        assert_eq!(hosts[0].addr, "10.0.0.1");
+        let ports: Vec<(u16, Option<&str>, bool)> = hosts[0]
+            .ports
+            .iter()
+            .map(|p| (p.port, p.service.as_deref(), p.tls))
+            .collect();
+        assert_eq!(
+            ports,
+            vec![
+                (445, Some("microsoft-ds"), false),
+                (8443, Some("http"), true)
+            ]
+        );
+
+        assert_eq!(hosts[1].addr, "fe80::1");
+        assert!(hosts[1].ports[0].tls);
+        assert_eq!(hosts[1].addr_port(443), "[fe80::1]:443");
+    }
+
+    #[test]
+    fn parse_masscan_xml_ports() {
+        let content = r#"<?xml version="1.0"?>
+<nmaprun scanner="masscan">
+<host endtime="1"><address addr="10.0.0.1" addrtype="ipv4"/><ports><port protocol="tcp" portid="445"><state state="open" reason="syn-ack"/></port></ports></host>
+<host endtime="1"><address addr="10.0.0.2" addrtype="ipv4"/><ports><port protocol="tcp" portid="445"><state state="open" reason="syn-ack"/></port></ports></host>
+<host endtime="1"><address addr="10.0.0.1" addrtype="ipv4"/><ports><port protocol="tcp" portid="80"><state state="open" reason="syn-ack"/></port></ports></host>
+</nmaprun>
+"#;
+        let mut reader =
+            ScanReader::new(ScanFormat::MasscanXml, content.as_bytes());
+        assert_eq!(reader.next().unwrap().unwrap().addr, "10.0.0.1");
+        assert!(!reader.done);
+
+        assert_eq!(
+            read_hosts(ScanFormat::MasscanXml, content),
+            vec!["10.0.0.1:445", "10.0.0.2:445", "10.0.0.1:80"]
+        );
+    }
+
+    #[test]
+    fn parse_gnmap_ports() {
+        let content = "# Nmap 7.80 scan initiated as: nmap -oG - 10.0.0.0/24
+Host: 10.0.0.1 (ws01.contoso.local)\tStatus: Up
+Host: 10.0.0.1 (ws01.contoso.local)\tPorts: 139/closed/tcp//netbios-ssn///, 445/open/tcp//microsoft-ds///, 80/filtered/tcp//http///, 137/open/udp//netbios-ns///, 8443/open/tcp//ssl|http///\tIgnored State: closed (995)
+Host: fe80::1 ()\tPorts: 443/open/tcp//https///
+Host: 10.0.0.3 ()\tPorts: 445/filtered/tcp//microsoft-ds///
+# Nmap done at Mon Jan  1 00:00:00 2024 -- 256 IP addresses (3 hosts up)
+";
+        let hosts: Vec<ScanHost> =
+            ScanReader::new(ScanFormat::Gnmap, content.as_bytes())
+                .map(|h| h.unwrap())
+                .collect();
+        assert_eq!(hosts.len(), 2);
+
+        assert_eq!(hosts[0].addr, "10.0.0.1");
+        let ports: Vec<(u16, Option<&str>, bool)> = hosts[0]
+            .ports
+            .iter()
+            .map(|p| (p.port, p.service.as_deref(), p.tls))
+            .collect();
+        assert_eq!(
+            ports,
+            vec![
+                (445, Some("microsoft-ds"), false),
+                (8443, Some("http"), true)
+            ]
+        );
+
+        assert_eq!(hosts[1].addr, "fe80::1");
+        assert!(hosts[1].ports[0].tls);
+    }
+
+    #[test]
+    fn parse_masscan_json_trailing_commas() {
+        // old masscan versions leave a comma after the last record
+        let content = r#"[
+{   "ip": "10.0.0.1",   "timestamp": "1", "ports": [ {"port": 445, "proto": "tcp", "status": "open", "reason": "syn-ack", "ttl": 128} ] },
+{   "ip": "fe80::1",   "timestamp": "1", "ports": [ {"port": 445, "proto": "tcp", "status": "open", "reason": "syn-ack", "ttl": 128} ] },
+{   "ip": "10.0.0.1",   "timestamp": "1", "ports": [ {"port": 137, "proto": "udp", "status": "open"} ] },
+{   "ip": "10.0.0.3",   "timestamp": "1", "ports": [ {"port": 445, "proto": "tcp", "status": "closed"} ] },
+{   "ip": "10.0.0.1",   "timestamp": "1", "ports": [ {"port": 80, "proto": "tcp", "status": "open", "reason": "syn-ack", "ttl": 128} ] },
+]
+"#;
+        assert_eq!(
+            read_hosts(ScanFormat::MasscanJson, content),
+            vec!["10.0.0.1:445", "fe80::1:445", "10.0.0.1:80"]
+        );
+
+        // valid JSON, with the records broken in several lines
+        let content = r#"[{"ip": "10.0.0.1",
+            "ports": [{"port": 445, "proto": "tcp", "status": "open",
+            "reason": "syn-ack"}]}, {"ip": "10.0.0.2", "ports": [
+            {"port": 139, "proto": "tcp", "status": "open"}]}]"#;
+        assert_eq!(
+            read_hosts(ScanFormat::MasscanJson, content),
+            vec!["10.0.0.1:445", "10.0.0.2:139"]
+        );
+    }
+
+    #[test]
+    fn parse_masscan_list_ports() {
+        let content = "#masscan
+open tcp 445 10.0.0.1 1612345678
+closed tcp 139 10.0.0.1 1612345678
+open udp 137 10.0.0.1 1612345678
+open tcp 445 fe80::1 1612345678
+# end
+";
+        assert_eq!(
+            read_hosts(ScanFormat::MasscanList, content),
+            vec!["10.0.0.1:445", "fe80::1:445"]
+        );
+    }
+}
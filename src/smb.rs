@@ -2,6 +2,9 @@ use crate::auth::extract_ntlm_challenge;
 use crate::auth::new_spnego_init2;
 use crate::challenge::Challenge;
 use crate::challenge::Host;
+use crate::challenge::Protocol;
+use crate::error::Error;
+use crate::readin::ScanHost;
 use ntlm::ChallengeMsg;
 use smb::smb1::negotiate::SMB_DIA_NT_LM;
 use smb::smb1::negotiate::SMB_DIA_SMB_2_002;
@@ -10,7 +13,7 @@ use smb::smb2::negotiate::SMB2_DIA_202;
 use smb::smb2::negotiate::SMB2_DIA_210;
 use smb::smb2::negotiate::SMB2_DIA_300;
 use smb::smb2::Smb2NegResp;
-use std::net::IpAddr;
+use std::io::{self, Read, Write};
 use std::net::SocketAddr;
 use std::net::TcpStream;
 use std::time::Duration;
@@ -35,47 +38,71 @@ use smb::net::{
 };
 use smb1::Smb1NegReq;
 
-use crate::dns;
+use crate::dns::DnsResolver;
 use smb::SmbNegResp;
 
-#[derive(Clone, Debug, Copy)]
+/// Port of the SMB direct hosting.
+pub const SMB_PORT: u16 = 445;
+
+/// Port of the NetBIOS session service, which requires a session request
+/// before sending SMB messages.
+pub const NETBIOS_SSN_PORT: u16 = 139;
+
+#[derive(Clone)]
 pub struct SmbOptions {
     pub timeout: Duration,
     pub port: u16,
+
+    /// Retrieve the challenge of every IP of the hostnames, instead of
+    /// only the first one.
+    pub all_ips: bool,
+
+    pub resolver: DnsResolver,
 }
 
+/// Retrieves the challenge of the host, which is already resolved.
 pub fn fetch_ntlm_challenge_smb(
-    host: String,
-    options: SmbOptions,
-) -> Result<Challenge, String> {
-    let host = match host.parse::<IpAddr>() {
-        Ok(ip) => Host::new(ip, None),
-        Err(_) => {
-            let ip = dns::resolve_hostname(&host)?;
-            Host::new(ip, Some(host))
-        }
-    };
+    host: Host,
+    port: u16,
+    timeout: Duration,
+) -> Result<Challenge, Error> {
+    let target_address = SocketAddr::new(host.ip, port);
+    let mut challenge =
+        Challenge::new(host.into(), challenge_smb(&target_address, timeout)?);
+    challenge.address = Some(target_address);
+    challenge.protocol = Some(Protocol::Smb);
 
-    let target_address = SocketAddr::new(host.ip, options.port);
-    return Ok(Challenge::new(
-        host.into(),
-        challenge_smb(&target_address, options.timeout)?,
-    ));
+    return Ok(challenge);
 }
 
 pub fn challenge_smb(
     addr: &SocketAddr,
     timeout: Duration,
-) -> Result<ChallengeMsg, String> {
-    let mut stream = TcpStream::connect_timeout(addr, timeout)
-        .map_err(|e| format!("Error connecting with '{}': {}", addr, e))?;
+) -> Result<ChallengeMsg, Error> {
+    let mut stream =
+        TcpStream::connect_timeout(addr, timeout).map_err(|e| {
+            Error::from_io(
+                &e,
+                format!("Error connecting with '{}': {}", addr, e),
+            )
+        })?;
 
     stream
         .set_read_timeout(Some(timeout))
         .expect("Invalid timeout to SMB stream");
 
+    if addr.port() == NETBIOS_SSN_PORT {
+        netbios_session_request(&mut stream).map_err(|e| {
+            Error::from_io(
+                &e,
+                format!("Error in NetBIOS session with '{}': {}", addr, e),
+            )
+        })?;
+    }
+
     let _ = smb_negotiate(&mut stream).map_err(|e| {
-        format!("Error in SMB negotiation with '{}': {}", addr, e)
+        let msg = format!("Error in SMB negotiation with '{}': {}", addr, e);
+        smb_error(e, msg)
     })?;
 
     // let neg_resp = match neg_resp {
@@ -83,10 +110,108 @@ pub fn challenge_smb(
     // };
 
     smb_negotiate2(&mut stream).map_err(|e| {
-        format!("Error in SMB2 negotiation with '{}': {}", addr, e)
+        let msg = format!("Error in SMB2 negotiation with '{}': {}", addr, e);
+        smb_error(e, msg)
     })?;
 
-    return smb_session_setup2(&mut stream);
+    return smb_session_setup2(&mut stream, addr);
+}
+
+/// Splits a target given as `host:port`, or `[ipv6]:port`. If no port is
+/// specified, the default one is returned.
+pub fn split_host_port(target: &str, default_port: u16) -> (String, u16) {
+    if let Ok(addr) = target.parse::<SocketAddr>() {
+        return (addr.ip().to_string(), addr.port());
+    }
+
+    if let Some((host, port)) = target.rsplit_once(':') {
+        if !host.contains(':') {
+            if let Ok(port) = port.parse() {
+                return (host.to_string(), port);
+            }
+        }
+    }
+
+    return (target.to_string(), default_port);
+}
+
+/// Selects the SMB port of the hosts found by a port scanner, preferring
+/// the direct hosting port over NetBIOS.
+pub fn smb_scan_targets(host: &ScanHost) -> Vec<String> {
+    let is_open = |port: u16, service: &str| {
+        host.ports
+            .iter()
+            .any(|p| p.port == port || p.service.as_deref() == Some(service))
+    };
+
+    if is_open(SMB_PORT, "microsoft-ds") {
+        let port = host
+            .ports
+            .iter()
+            .find(|p| p.service.as_deref() == Some("microsoft-ds"))
+            .map(|p| p.port)
+            .unwrap_or(SMB_PORT);
+        return vec![host.addr_port(port)];
+    }
+
+    if is_open(NETBIOS_SSN_PORT, "netbios-ssn") {
+        return vec![host.addr_port(NETBIOS_SSN_PORT)];
+    }
+
+    return Vec::new();
+}
+
+/// Sends the NetBIOS session request required to use SMB over the port
+/// 139, calling to the generic name *SMBSERVER.
+fn netbios_session_request(stream: &mut TcpStream) -> io::Result<()> {
+    let mut request = vec![0x81, 0x00, 0x00, 0x44];
+    request.extend(netbios_name("*SMBSERVER", 0x20));
+    request.extend(netbios_name("NTLM-INFO", 0x00));
+    stream.write_all(&request)?;
+
+    let mut header = [0; 4];
+    stream.read_exact(&mut header)?;
+    let mut body = vec![0; u16::from_be_bytes([header[2], header[3]]) as usize];
+    stream.read_exact(&mut body)?;
+
+    // 0x82 is the positive session response
+    if header[0] != 0x82 {
+        return Err(io::Error::new(
+            io::ErrorKind::ConnectionRefused,
+            format!(
+                "NetBIOS session refused (error 0x{:02x})",
+                body.first().unwrap_or(&0)
+            ),
+        ));
+    }
+
+    return Ok(());
+}
+
+/// Encodes a NetBIOS name, as defined in RFC 1001 section 14.1.
+fn netbios_name(name: &str, suffix: u8) -> Vec<u8> {
+    let mut raw = [b' '; 16];
+    for (i, b) in name.bytes().take(15).enumerate() {
+        raw[i] = b.to_ascii_uppercase();
+    }
+    raw[15] = suffix;
+
+    let mut encoded = vec![32];
+    for b in raw.iter() {
+        encoded.push(b'A' + (b >> 4));
+        encoded.push(b'A' + (b & 0x0f));
+    }
+    encoded.push(0);
+
+    return encoded;
+}
+
+/// Classifies an error produced in the SMB communication.
+fn smb_error(err: smb::Error, msg: String) -> Error {
+    match err {
+        smb::Error::IO(e) => Error::from_io(&e, msg),
+        _ => Error::Protocol(msg),
+    }
 }
 
 fn smb_negotiate(stream: &mut TcpStream) -> smb::Result<SmbNegResp> {
@@ -123,15 +248,22 @@ fn smb_negotiate2(stream: &mut TcpStream) -> smb::Result<Smb2NegResp> {
     return send_recv_negotiate2(stream, &neg2_req);
 }
 
-fn smb_session_setup2(stream: &mut TcpStream) -> Result<ChallengeMsg, String> {
+fn smb_session_setup2(
+    stream: &mut TcpStream,
+    addr: &SocketAddr,
+) -> Result<ChallengeMsg, Error> {
     let mut sess_req = Smb2SessionSetupReq::new();
     sess_req.header.message_id = 2;
     sess_req.body.security_mode = SMB2_NEGOTIATE_SIGNING_ENABLED;
 
     sess_req.body.buffer = new_spnego_init2().build();
 
-    let setup_resp = send_recv_session_setup2(stream, &sess_req)
-        .map_err(|_| format!("Error in SMB2 session setup"))?;
+    let setup_resp =
+        send_recv_session_setup2(stream, &sess_req).map_err(|e| {
+            let msg =
+                format!("Error in SMB2 session setup with '{}': {}", addr, e);
+            smb_error(e, msg)
+        })?;
 
     return extract_ntlm_challenge(&setup_resp.body.buffer);
 }